@@ -0,0 +1,128 @@
+use std::fs::{self, File};
+
+use serde::{Deserialize, Serialize};
+
+// A known wrapper that can be added to a config as a ConfigOption
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CatalogueEntry {
+    pub name: String,
+    pub description: String,
+    pub placeholder: String,
+    pub replace_with: String,
+}
+
+impl CatalogueEntry {
+    fn new(name: &str, description: &str, placeholder: &str, replace_with: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            description: description.to_string(),
+            placeholder: placeholder.to_string(),
+            replace_with: replace_with.to_string(),
+        }
+    }
+}
+
+fn builtin() -> Vec<CatalogueEntry> {
+    vec![
+        CatalogueEntry::new(
+            "gamemoderun",
+            "Apply Feral GameMode optimizations while the game is running",
+            "%gm%",
+            "gamemoderun",
+        ),
+        CatalogueEntry::new(
+            "gamescope",
+            "Run the game inside the gamescope micro-compositor",
+            "%gs%",
+            "gamescope -f --",
+        ),
+        CatalogueEntry::new(
+            "mangohud",
+            "Show the MangoHud performance overlay",
+            "%mh%",
+            "mangohud",
+        ),
+        CatalogueEntry::new(
+            "prime-run",
+            "Render on the dedicated NVIDIA GPU of a PRIME laptop",
+            "%pr%",
+            "prime-run",
+        ),
+        CatalogueEntry::new(
+            "obs-vkcapture",
+            "Capture Vulkan games with the OBS game capture plugin",
+            "%ov%",
+            "obs-vkcapture",
+        ),
+        CatalogueEntry::new(
+            "obs-glcapture",
+            "Capture OpenGL games with the OBS game capture plugin",
+            "%og%",
+            "obs-glcapture",
+        ),
+        CatalogueEntry::new(
+            "strace",
+            "Trace the system calls of the game and everything it starts",
+            "%st%",
+            "strace -f -o /tmp/steamrunner_strace.log",
+        ),
+        CatalogueEntry::new(
+            "taskset",
+            "Restrict the game to the specified CPU cores",
+            "%ts%",
+            "taskset -c 0-3",
+        ),
+        CatalogueEntry::new(
+            "nice",
+            "Run the game with a lower scheduling priority",
+            "%ni%",
+            "nice -n 5",
+        ),
+        CatalogueEntry::new(
+            "ionice",
+            "Run the game with the best-effort I/O scheduling class",
+            "%io%",
+            "ionice -c 2 -n 0",
+        ),
+    ]
+}
+
+// Load the built in catalogue and extend it with the entries in the YAML files of the catalogue
+// directory. Each file holds a list of entries, an entry with the name of a built in one replaces it
+pub fn load(config_dir: &String) -> Vec<CatalogueEntry> {
+    let mut catalogue = builtin();
+
+    let mut paths: Vec<_> = fs::read_dir(format!("{}/catalogue", config_dir))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension()
+                .map_or(false, |extension| extension == "yaml" || extension == "yml")
+        })
+        .collect();
+    paths.sort();
+
+    for path in paths {
+        let entries: Vec<CatalogueEntry> = match File::open(&path)
+            .map_err(|why| why.to_string())
+            .and_then(|file| serde_yaml::from_reader(file).map_err(|why| why.to_string()))
+        {
+            Ok(entries) => entries,
+            Err(why) => {
+                println!("Failed to load catalogue {}: {}", path.display(), why);
+                continue;
+            }
+        };
+
+        for entry in entries {
+            match catalogue.iter_mut().find(|other| other.name == entry.name) {
+                Some(other) => *other = entry,
+                None => catalogue.push(entry),
+            }
+        }
+    }
+
+    catalogue
+}
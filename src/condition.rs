@@ -0,0 +1,299 @@
+use std::{env, fs, path::PathBuf};
+
+use crate::session_log::SessionLog;
+
+// Conditions are expressions made of these checks, combined with `!`, `&&`, `||` and parentheses:
+//   hostname(name)   the hostname of the machine is name
+//   battery          the machine is running on battery
+//   has(binary)      binary can be found in $PATH
+//   env(VAR)         the environment variable VAR is set and not empty
+//   env(VAR=value)   the environment variable VAR is set to value
+// For example `hostname(laptop) && !battery || env(FORCE_HUD)`
+pub fn evaluate(condition: &str) -> Result<bool, String> {
+    let mut parser = Parser {
+        chars: condition.chars().collect(),
+        pos: 0,
+    };
+    let result = parser.parse_or()?;
+
+    parser.skip_whitespace();
+    if parser.pos < parser.chars.len() {
+        return Err(format!("Unexpected `{}`", parser.rest()));
+    }
+
+    Ok(result)
+}
+
+// Evaluate the condition of an option or command at launch and log the result. An invalid
+// condition counts as not holding
+pub fn check(condition: &str, subject: &str, log: &SessionLog) -> bool {
+    match evaluate(condition) {
+        Ok(holds) => {
+            log.log(&format!(
+                "Condition `{}` of {} {}",
+                condition,
+                subject,
+                if holds { "holds" } else { "does not hold" }
+            ));
+            holds
+        }
+        Err(why) => {
+            log.log(&format!(
+                "Invalid condition `{}` of {}: {}, treating it as not holding",
+                condition, subject, why
+            ));
+            false
+        }
+    }
+}
+
+// Short description of the current state of a condition for the config editor
+pub fn describe(condition: &str) -> String {
+    if condition.trim().is_empty() {
+        return String::new();
+    }
+    match evaluate(condition) {
+        Ok(true) => "Holds".to_string(),
+        Ok(false) => "Does not hold".to_string(),
+        Err(why) => format!("Invalid: {}", why),
+    }
+}
+
+// Find an executable in $PATH
+pub fn find_in_path(binary: &str) -> Option<PathBuf> {
+    // Paths are used as is, like a shell would
+    if binary.contains('/') {
+        let path = PathBuf::from(binary);
+        return if path.is_file() { Some(path) } else { None };
+    }
+
+    env::var_os("PATH").and_then(|path| {
+        env::split_paths(&path)
+            .map(|dir| dir.join(binary))
+            .find(|candidate| candidate.is_file())
+    })
+}
+
+// Check whether the machine runs on battery, meaning it has a battery and no online power supply
+fn on_battery() -> bool {
+    let mut has_battery = false;
+
+    for entry in fs::read_dir("/sys/class/power_supply")
+        .into_iter()
+        .flatten()
+        .flatten()
+    {
+        let read = |name: &str| {
+            fs::read_to_string(entry.path().join(name))
+                .map(|value| value.trim().to_string())
+                .unwrap_or_default()
+        };
+
+        match read("type").as_str() {
+            "Battery" => has_battery = true,
+            "Mains" | "USB" => {
+                if read("online") == "1" {
+                    return false;
+                }
+            }
+            _ => (),
+        }
+    }
+
+    has_battery
+}
+
+fn hostname() -> String {
+    fs::read_to_string("/proc/sys/kernel/hostname")
+        .map(|hostname| hostname.trim().to_string())
+        .unwrap_or_default()
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn rest(&self) -> String {
+        self.chars[self.pos..].iter().collect()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.pos < self.chars.len() && self.chars[self.pos].is_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    // Consume the token if it comes next
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        if self.rest().starts_with(token) {
+            self.pos += token.chars().count();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<bool, String> {
+        let mut result = self.parse_and()?;
+        while self.eat("||") {
+            // Both sides are always parsed, so errors are found regardless of the values
+            let right = self.parse_and()?;
+            result = result || right;
+        }
+        Ok(result)
+    }
+
+    fn parse_and(&mut self) -> Result<bool, String> {
+        let mut result = self.parse_unary()?;
+        while self.eat("&&") {
+            let right = self.parse_unary()?;
+            result = result && right;
+        }
+        Ok(result)
+    }
+
+    fn parse_unary(&mut self) -> Result<bool, String> {
+        if self.eat("!") {
+            return Ok(!self.parse_unary()?);
+        }
+        if self.eat("(") {
+            let result = self.parse_or()?;
+            if !self.eat(")") {
+                return Err("Missing `)`".to_string());
+            }
+            return Ok(result);
+        }
+        self.parse_check()
+    }
+
+    fn parse_check(&mut self) -> Result<bool, String> {
+        self.skip_whitespace();
+        let start = self.pos;
+        while self.pos < self.chars.len()
+            && (self.chars[self.pos].is_alphanumeric() || self.chars[self.pos] == '_')
+        {
+            self.pos += 1;
+        }
+        let name: String = self.chars[start..self.pos].iter().collect();
+        if name.is_empty() {
+            return Err(if self.pos < self.chars.len() {
+                format!("Unexpected `{}`", self.rest())
+            } else {
+                "Unexpected end of condition".to_string()
+            });
+        }
+
+        // The argument is everything up to the closing parenthesis
+        let argument = if self.eat("(") {
+            let start = self.pos;
+            while self.pos < self.chars.len() && self.chars[self.pos] != ')' {
+                self.pos += 1;
+            }
+            if self.pos == self.chars.len() {
+                return Err(format!("Missing `)` after {}", name));
+            }
+            let argument: String = self.chars[start..self.pos].iter().collect();
+            self.pos += 1;
+            Some(argument.trim().to_string())
+        } else {
+            None
+        };
+
+        match (name.as_str(), argument) {
+            ("hostname", Some(expected)) => Ok(hostname() == expected),
+            ("battery", None) => Ok(on_battery()),
+            ("has", Some(binary)) => Ok(find_in_path(&binary).is_some()),
+            ("env", Some(variable)) => Ok(match variable.split_once('=') {
+                Some((variable, expected)) => env::var(variable.trim())
+                    .map(|value| value == expected.trim())
+                    .unwrap_or(false),
+                None => env::var(&variable)
+                    .map(|value| !value.is_empty())
+                    .unwrap_or(false),
+            }),
+            ("hostname", None) | ("has", None) | ("env", None) => {
+                Err(format!("{} needs an argument", name))
+            }
+            ("battery", Some(_)) => Err("battery takes no argument".to_string()),
+            _ => Err(format!("Unknown check {}", name)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn env_checks() {
+        // Every test uses variables of its own, as the tests run in parallel
+        env::set_var("STEAMRUNNER_TEST_ENV_SET", "yes");
+        env::set_var("STEAMRUNNER_TEST_ENV_EMPTY", "");
+
+        assert_eq!(evaluate("env(STEAMRUNNER_TEST_ENV_SET)"), Ok(true));
+        assert_eq!(evaluate("env(STEAMRUNNER_TEST_ENV_EMPTY)"), Ok(false));
+        assert_eq!(evaluate("env(STEAMRUNNER_TEST_ENV_UNSET)"), Ok(false));
+        assert_eq!(evaluate("env(STEAMRUNNER_TEST_ENV_SET=yes)"), Ok(true));
+        assert_eq!(evaluate("env( STEAMRUNNER_TEST_ENV_SET = yes )"), Ok(true));
+        assert_eq!(evaluate("env(STEAMRUNNER_TEST_ENV_SET=no)"), Ok(false));
+    }
+
+    #[test]
+    fn has_checks() {
+        assert_eq!(evaluate("has(sh)"), Ok(true));
+        assert_eq!(evaluate("has(steamrunner-test-no-such-binary)"), Ok(false));
+        assert_eq!(evaluate("has(/steamrunner-test/no/such/path)"), Ok(false));
+    }
+
+    #[test]
+    fn operators() {
+        env::set_var("STEAMRUNNER_TEST_OPS_A", "1");
+        let (a, b) = ("env(STEAMRUNNER_TEST_OPS_A)", "env(STEAMRUNNER_TEST_OPS_B)");
+
+        assert_eq!(evaluate(&format!("!{}", a)), Ok(false));
+        assert_eq!(evaluate(&format!("!!{}", a)), Ok(true));
+        assert_eq!(evaluate(&format!("{} && {}", a, b)), Ok(false));
+        assert_eq!(evaluate(&format!("{} || {}", b, a)), Ok(true));
+        // && binds tighter than ||
+        assert_eq!(evaluate(&format!("{} || {} && {}", a, b, b)), Ok(true));
+        assert_eq!(evaluate(&format!("({} || {}) && {}", a, b, b)), Ok(false));
+        assert_eq!(evaluate(&format!("!({} && {})", a, b)), Ok(true));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(evaluate(""), Err("Unexpected end of condition".to_string()));
+        assert_eq!(
+            evaluate("frobnicate"),
+            Err("Unknown check frobnicate".to_string())
+        );
+        assert_eq!(evaluate("env"), Err("env needs an argument".to_string()));
+        assert_eq!(
+            evaluate("battery(now)"),
+            Err("battery takes no argument".to_string())
+        );
+        assert_eq!(evaluate("has(sh"), Err("Missing `)` after has".to_string()));
+        assert_eq!(evaluate("(has(sh)"), Err("Missing `)`".to_string()));
+        assert_eq!(
+            evaluate("has(sh) has(sh)"),
+            Err("Unexpected `has(sh)`".to_string())
+        );
+        assert_eq!(
+            evaluate("has(sh) &&"),
+            Err("Unexpected end of condition".to_string())
+        );
+        // Errors on the side that doesn't decide the result are still found
+        assert!(evaluate("has(sh) || nope").is_err());
+    }
+
+    #[test]
+    fn describe_for_editor() {
+        assert_eq!(describe("  "), "");
+        assert_eq!(describe("has(sh)"), "Holds");
+        assert_eq!(describe("!has(sh)"), "Does not hold");
+        assert_eq!(describe("nope"), "Invalid: Unknown check nope");
+    }
+}
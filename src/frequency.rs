@@ -0,0 +1,109 @@
+use std::{fs, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    game_config::ConfigCommand,
+    shared,
+    time::{self, now},
+};
+
+const DAY: u64 = 24 * 60 * 60;
+
+// How often a pre-launch or post-exit command runs. A command only counts as having run when it
+// succeeded, so a failed run is retried on the next launch
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Frequency {
+    EveryLaunch,
+    // Until the runtime dir is cleared, which happens on reboot or when the user logs out
+    OncePerBoot,
+    // At most once in 24 hours
+    OncePerDay,
+}
+
+impl Default for Frequency {
+    fn default() -> Self {
+        Frequency::EveryLaunch
+    }
+}
+
+impl Frequency {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Frequency::EveryLaunch => "every launch",
+            Frequency::OncePerBoot => "once per boot",
+            Frequency::OncePerDay => "once per day",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "every launch" => Some(Frequency::EveryLaunch),
+            "once per boot" => Some(Frequency::OncePerBoot),
+            "once per day" => Some(Frequency::OncePerDay),
+            _ => None,
+        }
+    }
+}
+
+// Commands are told apart by their command line, the same command in different games or phases
+// shares its markers. FNV-1a, as the file names have to stay the same between builds
+fn key(command: &ConfigCommand) -> String {
+    let hash = command
+        .command
+        .bytes()
+        .fold(0xcbf29ce484222325u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        });
+    format!("{:016x}", hash)
+}
+
+// The time of the last successful run of every command is kept in ran/ in the state dir
+fn marker_path(command: &ConfigCommand, state_dir: &str) -> String {
+    format!("{}/ran/{}", state_dir, key(command))
+}
+
+// Commands that ran since boot also get a marker in the runtime dir
+fn boot_marker_path(command: &ConfigCommand) -> String {
+    format!("{}/ran/{}", shared::runtime_dir(), key(command))
+}
+
+// Seconds since the epoch of the last successful run of the command
+pub fn last_ran(command: &ConfigCommand, state_dir: &str) -> Option<u64> {
+    fs::read_to_string(marker_path(command, state_dir))
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+// Whether the command has to run on this launch
+pub fn is_due(command: &ConfigCommand, state_dir: &str) -> bool {
+    match command.frequency {
+        Frequency::EveryLaunch => true,
+        Frequency::OncePerBoot => !Path::new(&boot_marker_path(command)).exists(),
+        Frequency::OncePerDay => last_ran(command, state_dir)
+            .map_or(true, |last_ran| now().saturating_sub(last_ran) >= DAY),
+    }
+}
+
+// Record a successful run of the command
+pub fn mark_ran(command: &ConfigCommand, state_dir: &str) {
+    for path in [marker_path(command, state_dir), boot_marker_path(command)] {
+        let written = Path::new(&path)
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(&path, now().to_string()));
+        if let Err(why) = written {
+            println!("Failed to write {}: {}", path, why);
+        }
+    }
+}
+
+// When the command last ran, for the config editor
+pub fn describe_last_ran(command: &ConfigCommand, state_dir: &str) -> String {
+    match last_ran(command, state_dir) {
+        Some(last_ran) => format!("Last ran {}", time::format_local(last_ran)),
+        None => "Never ran".to_string(),
+    }
+}
@@ -1,7 +1,23 @@
-use std::{fs::File, io::Write};
+use std::{collections::BTreeMap, fs::File, io::Write};
 
 use serde::{Deserialize, Serialize};
 
+use crate::{
+    catalogue::CatalogueEntry,
+    condition,
+    frequency::Frequency,
+    gamescope::{self, GamescopeConfig},
+    mangohud::MangoHudConfig,
+    overlay::FileOverlay,
+    playtime::PlaytimeLimits,
+    preflight::PreflightCheck,
+    proton::ProtonConfig,
+    scheduling::SchedulingConfig,
+    session_log::SessionLog,
+    snapshot::SnapshotConfig,
+    tools,
+};
+
 // Struct to contain the values for one specific option
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct ConfigOption {
@@ -9,6 +25,12 @@ pub struct ConfigOption {
     pub replace_with: String,
     pub enabled: bool,
     pub modified: bool,
+    // Only apply the option when this condition holds, see condition.rs for the syntax
+    #[serde(default)]
+    pub condition: String,
+    // Number of launches the option is still enabled for regardless of enabled, 0 for none
+    #[serde(default)]
+    pub next_launches: u32,
 }
 
 impl ConfigOption {
@@ -18,6 +40,8 @@ impl ConfigOption {
             replace_with: replace_with.clone(),
             enabled: enabled.clone(),
             modified: modified.clone(),
+            condition: String::new(),
+            next_launches: 0,
         }
     }
 }
@@ -27,6 +51,189 @@ pub struct ConfigCommand {
     pub command: String,
     pub enabled: bool,
     pub modified: bool,
+    // Optional name other commands can refer to in their `after` list
+    #[serde(default)]
+    pub name: String,
+    // Parallel commands don't wait for the commands before them, only for the ones in `after`
+    #[serde(default)]
+    pub parallel: bool,
+    #[serde(default)]
+    pub after: Vec<String>,
+    // Environment variables set for this command only
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    // Directory the command is run in, empty to use the one steamrunner was started in
+    #[serde(default)]
+    pub working_dir: String,
+    #[serde(default)]
+    pub shell: Shell,
+    // Remove the Steam overlay and Steam runtime variables, which break host tools
+    #[serde(default)]
+    pub strip_steam_env: bool,
+    // Only run the command when this condition holds, see condition.rs for the syntax
+    #[serde(default)]
+    pub condition: String,
+    #[serde(default)]
+    pub frequency: Frequency,
+    // Number of launches the command is still enabled for regardless of enabled, 0 for none
+    #[serde(default)]
+    pub next_launches: u32,
+}
+
+// A setup command together with the command that undoes it. The undo command runs after the game
+// has exited or the launch was aborted, but only when the setup command succeeded
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct PairedAction {
+    pub name: String,
+    pub setup: String,
+    pub undo: String,
+    pub enabled: bool,
+    #[serde(default)]
+    pub condition: String,
+    // Shared actions are set up by the first running game that needs them and undone by the last
+    // one, going by name across steamrunner processes
+    #[serde(default)]
+    pub shared: bool,
+    // Both commands are run like a ConfigCommand with these settings
+    #[serde(default)]
+    pub shell: Shell,
+    #[serde(default)]
+    pub working_dir: String,
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    #[serde(default)]
+    pub strip_steam_env: bool,
+}
+
+// What a ConfigCommand is run with
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Shell {
+    Sh,
+    Bash,
+    Fish,
+    // Run the command directly without a shell. The arguments are split like a shell would, with
+    // quotes and backslash escapes, but without any expansion
+    Exec,
+}
+
+impl Default for Shell {
+    fn default() -> Self {
+        Shell::Sh
+    }
+}
+
+impl Shell {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Shell::Sh => "sh",
+            Shell::Bash => "bash",
+            Shell::Fish => "fish",
+            Shell::Exec => "exec",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "sh" => Some(Shell::Sh),
+            "bash" => Some(Shell::Bash),
+            "fish" => Some(Shell::Fish),
+            "exec" => Some(Shell::Exec),
+            _ => None,
+        }
+    }
+}
+
+// Settings for what to do with processes the game leaves behind after the launched command exits
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProcessCleanup {
+    // Kill the remaining processes this many seconds after the launched command exited, 0 waits
+    // for them forever
+    pub kill_leftovers_after: u64,
+    // Names of processes that linger after the game has closed (like wineserver), these are
+    // killed right away once they are the only processes left
+    pub stragglers: Vec<String>,
+    // Seconds to wait for the game to exit after forwarding a signal to it before killing it
+    #[serde(default = "default_stop_timeout")]
+    pub stop_timeout: u64,
+}
+
+fn default_stop_timeout() -> u64 {
+    10
+}
+
+impl Default for ProcessCleanup {
+    fn default() -> Self {
+        Self {
+            kill_leftovers_after: 0,
+            stragglers: vec!["wineserver".to_string()],
+            stop_timeout: default_stop_timeout(),
+        }
+    }
+}
+
+// What to do with the post-exit commands of interrupted sessions, read from the global config
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum InterruptedSessionAction {
+    // Ask on the Start Menu (or with a notification when the gui is disabled)
+    Ask,
+    Run,
+    Discard,
+}
+
+impl Default for InterruptedSessionAction {
+    fn default() -> Self {
+        InterruptedSessionAction::Ask
+    }
+}
+
+// The tweaks turned off when launching in safe mode, like after the game was updated. Each one can
+// be kept on by setting it to false
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct SafeModeConfig {
+    // The placeholder options, which hold the wrappers. The launch command is reduced to %command%
+    pub options: bool,
+    pub gamescope: bool,
+    pub mangohud: bool,
+    pub proton: bool,
+    pub overlays: bool,
+    // The pre-launch and post-exit commands and the paired actions
+    pub commands: bool,
+    // The scheduling settings and the GPU selection
+    pub scheduling: bool,
+    // Offer to relaunch in safe mode when the game exits with an error within this many seconds,
+    // 0 never does
+    pub fast_failure: u64,
+}
+
+impl Default for SafeModeConfig {
+    fn default() -> Self {
+        Self {
+            options: true,
+            gamescope: true,
+            mangohud: true,
+            proton: true,
+            overlays: true,
+            commands: true,
+            scheduling: true,
+            fast_failure: 5,
+        }
+    }
+}
+
+// What to do before launch when the tool of an enabled option can't be found
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum MissingToolAction {
+    Ignore,
+    Warn,
+    // Warn and disable the option for this launch
+    Disable,
+}
+
+impl Default for MissingToolAction {
+    fn default() -> Self {
+        MissingToolAction::Warn
+    }
 }
 
 // Entire struct to contain all ConfigOptions for the game specific config or the global config
@@ -38,6 +245,41 @@ pub struct GameConfig {
     pub placeholder_map: Vec<ConfigOption>,
     pub pre_launch_commands: Vec<ConfigCommand>,
     pub post_exit_commands: Vec<ConfigCommand>,
+    #[serde(default)]
+    pub paired_actions: Vec<PairedAction>,
+    #[serde(default)]
+    pub process_cleanup: ProcessCleanup,
+    #[serde(default)]
+    pub interrupted_sessions: InterruptedSessionAction,
+    #[serde(default)]
+    pub missing_tools: MissingToolAction,
+    #[serde(default)]
+    pub gamescope: GamescopeConfig,
+    #[serde(default)]
+    pub mangohud: MangoHudConfig,
+    #[serde(default)]
+    pub proton: ProtonConfig,
+    #[serde(default)]
+    pub scheduling: SchedulingConfig,
+    #[serde(default)]
+    pub snapshots: SnapshotConfig,
+    #[serde(default)]
+    pub overlays: Vec<FileOverlay>,
+    #[serde(default)]
+    pub preflight: Vec<PreflightCheck>,
+    #[serde(default)]
+    pub playtime: PlaytimeLimits,
+    // PCI address of the GPU the game renders on, empty for the default one
+    #[serde(default)]
+    pub render_on: String,
+    #[serde(default)]
+    pub safe_mode: SafeModeConfig,
+    // Build id of the game on the previous launch, to notice when Steam updated it
+    #[serde(default)]
+    pub last_build_id: String,
+    // Directory the GPUs are read from instead of /sys, only read from the global config
+    #[serde(default)]
+    pub sysfs_root: String,
 }
 
 impl GameConfig {
@@ -53,10 +295,307 @@ impl GameConfig {
                 launch_command = launch_command.replace(&item.placeholder, "");
             }
         }
+        launch_command = launch_command.replace(
+            gamescope::PLACEHOLDER,
+            &if self.gamescope.enabled {
+                self.gamescope.command_line()
+            } else {
+                String::new()
+            },
+        );
         launch_command = launch_command.replace("%command%", &command); // Add the actual command supplied by steam
 
         launch_command
     }
+    // Environment variables the game is started with, generated from the structured settings. The
+    // Proton settings only apply to games started through Proton
+    pub fn launch_env(&self, proton: bool) -> BTreeMap<String, String> {
+        let mut env = if proton {
+            self.proton.env()
+        } else {
+            BTreeMap::new()
+        };
+
+        if let Some(mangohud_config) = self.mangohud.config_string() {
+            env.insert("MANGOHUD_CONFIG".to_string(), mangohud_config);
+        }
+
+        env
+    }
+    // Enable the options and commands that are turned on for the next few launches, before their
+    // conditions are checked
+    pub fn apply_next_launches(&mut self, log: &SessionLog) {
+        for option in &mut self.placeholder_map {
+            if option.next_launches > 0 && !option.enabled {
+                option.enabled = true;
+                log.log(&format!(
+                    "Enabled option {} for this launch, {} launches left",
+                    option.placeholder, option.next_launches
+                ));
+            }
+        }
+        for command in self
+            .pre_launch_commands
+            .iter_mut()
+            .chain(self.post_exit_commands.iter_mut())
+        {
+            if command.next_launches > 0 && !command.enabled {
+                command.enabled = true;
+                log.log(&format!(
+                    "Enabled command `{}` for this launch, {} launches left",
+                    command.command, command.next_launches
+                ));
+            }
+        }
+    }
+
+    // Count a launch off the options and commands that are turned on for the next few launches.
+    // Only the ones that were actually enabled in the config the game was launched with count, not
+    // the ones a condition or safe mode turned off. Returns whether any counter changed, so the
+    // config has to be saved
+    pub fn count_launch(&mut self, launched: &GameConfig, log: &SessionLog) -> bool {
+        let mut changed = false;
+        let counters = self
+            .placeholder_map
+            .iter_mut()
+            .zip(&launched.placeholder_map)
+            .filter(|(_, launched)| launched.enabled)
+            .map(|(option, _)| {
+                (
+                    &mut option.next_launches,
+                    format!("option {}", option.placeholder),
+                )
+            })
+            .chain(
+                self.pre_launch_commands
+                    .iter_mut()
+                    .zip(&launched.pre_launch_commands)
+                    .chain(
+                        self.post_exit_commands
+                            .iter_mut()
+                            .zip(&launched.post_exit_commands),
+                    )
+                    .filter(|(_, launched)| launched.enabled)
+                    .map(|(command, _)| {
+                        (
+                            &mut command.next_launches,
+                            format!("command `{}`", command.command),
+                        )
+                    }),
+            );
+        for (next_launches, name) in counters.filter(|(next_launches, _)| **next_launches > 0) {
+            *next_launches -= 1;
+            changed = true;
+            if *next_launches == 0 {
+                log.log(&format!("The launches enabling {} are used up", name));
+            }
+        }
+
+        changed
+    }
+
+    // Turn on the option with the placeholder, or the commands with the name or command line, for
+    // the next launches. Fails when nothing matches
+    pub fn enable_for_next_launches(&mut self, item: &str, launches: u32) -> Result<(), String> {
+        let mut found = false;
+        for option in &mut self.placeholder_map {
+            if option.placeholder == item {
+                option.next_launches = launches;
+                found = true;
+            }
+        }
+        for command in self
+            .pre_launch_commands
+            .iter_mut()
+            .chain(self.post_exit_commands.iter_mut())
+        {
+            if command.command == item || (!command.name.is_empty() && command.name == item) {
+                command.next_launches = launches;
+                found = true;
+            }
+        }
+
+        if found {
+            Ok(())
+        } else {
+            Err(format!("There is no option or command {}", item))
+        }
+    }
+
+    // Disable the options and commands whose condition doesn't hold right now, the config is then
+    // used as is for the launch
+    pub fn apply_conditions(&mut self, log: &SessionLog) {
+        for option in &mut self.placeholder_map {
+            if option.enabled && !option.condition.trim().is_empty() {
+                option.enabled = condition::check(
+                    &option.condition,
+                    &format!("option {}", option.placeholder),
+                    log,
+                );
+            }
+        }
+        for command in self
+            .pre_launch_commands
+            .iter_mut()
+            .chain(self.post_exit_commands.iter_mut())
+        {
+            if command.enabled && !command.condition.trim().is_empty() {
+                command.enabled = condition::check(
+                    &command.condition,
+                    &format!("command `{}`", command.command),
+                    log,
+                );
+            }
+        }
+        for action in &mut self.paired_actions {
+            if action.enabled && !action.condition.trim().is_empty() {
+                action.enabled = condition::check(
+                    &action.condition,
+                    &format!("paired action {}", action.name),
+                    log,
+                );
+            }
+        }
+    }
+    // Turn off the tweaks selected in the safe mode settings for this launch
+    pub fn apply_safe_mode(&mut self, log: &SessionLog) {
+        let safe_mode = self.safe_mode.clone();
+        let mut disabled = Vec::new();
+
+        if safe_mode.options {
+            for option in &mut self.placeholder_map {
+                option.enabled = false;
+            }
+            // Gamescope has a placeholder of its own, which stays if gamescope is kept on
+            self.placeholder_launch_command = if !safe_mode.gamescope
+                && self
+                    .placeholder_launch_command
+                    .contains(gamescope::PLACEHOLDER)
+            {
+                format!("{} %command%", gamescope::PLACEHOLDER)
+            } else {
+                "%command%".to_string()
+            };
+            disabled.push("options");
+        }
+        if safe_mode.gamescope {
+            self.gamescope.enabled = false;
+            disabled.push("gamescope");
+        }
+        if safe_mode.mangohud {
+            self.mangohud.enabled = Some(false);
+            disabled.push("MangoHud settings");
+        }
+        if safe_mode.proton {
+            self.proton = Default::default();
+            disabled.push("Proton settings");
+        }
+        if safe_mode.overlays {
+            self.overlays.clear();
+            disabled.push("overlays");
+        }
+        if safe_mode.commands {
+            for command in self
+                .pre_launch_commands
+                .iter_mut()
+                .chain(self.post_exit_commands.iter_mut())
+            {
+                command.enabled = false;
+            }
+            for action in &mut self.paired_actions {
+                action.enabled = false;
+            }
+            disabled.push("commands");
+        }
+        if safe_mode.scheduling {
+            self.scheduling = Default::default();
+            self.render_on.clear();
+            disabled.push("scheduling and GPU selection");
+        }
+
+        if disabled.is_empty() {
+            log.log("Safe mode, but the safe mode settings turn nothing off");
+        } else {
+            log.log(&format!("Safe mode, disabled the {}", disabled.join(", ")));
+        }
+    }
+    // Find the enabled options whose tool can't be found in $PATH, and disable them if the config
+    // says so. Returns the missing tools
+    pub fn check_tools(&mut self, log: &SessionLog) -> Vec<String> {
+        let mut missing = Vec::new();
+
+        for option in &mut self.placeholder_map {
+            if !option.enabled {
+                continue;
+            }
+            if let Some(tool) = tools::tool_name(&option.replace_with) {
+                if condition::find_in_path(&tool).is_none() {
+                    log.log(&format!(
+                        "Tool {} of option {} is missing",
+                        tool, option.placeholder
+                    ));
+                    if self.missing_tools == MissingToolAction::Disable {
+                        option.enabled = false;
+                    }
+                    missing.push(tool);
+                }
+            }
+        }
+
+        missing
+    }
+    // Add the catalogue entry as an enabled option, and put its placeholder in front of %command%
+    // in the launch command so it wraps the game. The placeholder gets a number if already in use
+    pub fn add_from_catalogue(&mut self, entry: &CatalogueEntry) {
+        let mut placeholder = entry.placeholder.clone();
+        let mut number = 2;
+        while self
+            .placeholder_map
+            .iter()
+            .any(|option| option.placeholder == placeholder)
+        {
+            placeholder = format!("{}{}%", entry.placeholder.trim_end_matches('%'), number);
+            number += 1;
+        }
+
+        self.placeholder_map.push(ConfigOption::new(
+            &placeholder,
+            &entry.replace_with,
+            true,
+            true,
+        ));
+
+        self.placeholder_launch_command = if self.placeholder_launch_command.contains("%command%") {
+            self.placeholder_launch_command.replacen(
+                "%command%",
+                &format!("{} %command%", placeholder),
+                1,
+            )
+        } else {
+            format!("{} {}", placeholder, self.placeholder_launch_command)
+        };
+        self.launch_command_modified = true;
+    }
+    // Turn the first option running gamescope into the structured gamescope settings, replacing its
+    // placeholder in the launch command. Returns false if there is no such option
+    pub fn import_gamescope(&mut self) -> bool {
+        let index = match self.placeholder_map.iter().position(|option| {
+            tools::tool_name(&option.replace_with).map_or(false, |tool| tool.ends_with("gamescope"))
+        }) {
+            Some(index) => index,
+            None => return false,
+        };
+        let option = self.placeholder_map.remove(index);
+
+        self.gamescope = GamescopeConfig::parse(&option.replace_with);
+        self.gamescope.enabled = option.enabled;
+        self.placeholder_launch_command = self
+            .placeholder_launch_command
+            .replace(&option.placeholder, gamescope::PLACEHOLDER);
+        self.launch_command_modified = true;
+
+        true
+    }
     // Load the config file from the specified path
     pub fn load(path: &String) -> Self {
         let file = File::open(path).expect("Could not load file");
@@ -70,6 +609,11 @@ impl GameConfig {
             .unwrap();
     }
 
+    // Add the options, commands, paired actions, overlays and preflight checks of the global config
+    // that the game config doesn't have yet. The gamescope, Proton, scheduling, render_on, safe_mode
+    // and process_cleanup settings are not merged, they are only copied from the global config when
+    // the game config is created and belong to the game from then on. MangoHud settings the game
+    // leaves empty follow the global config at launch instead
     pub fn merge_with(&mut self, config: &GameConfig) {
         for config_opt in &config.placeholder_map {
             let mut replaced = false;
@@ -109,6 +653,31 @@ impl GameConfig {
                 self.post_exit_commands.push((*config_cmd).clone());
             }
         }
+        for config_action in &config.paired_actions {
+            if !self
+                .paired_actions
+                .iter()
+                .any(|self_action| self_action.setup == config_action.setup)
+            {
+                self.paired_actions.push((*config_action).clone());
+            }
+        }
+        for config_overlay in &config.overlays {
+            if !self
+                .overlays
+                .iter()
+                .any(|self_overlay| self_overlay.target == config_overlay.target)
+            {
+                self.overlays.push((*config_overlay).clone());
+            }
+        }
+        for config_check in &config.preflight {
+            if !self.preflight.iter().any(|self_check| {
+                self_check.kind == config_check.kind && self_check.target == config_check.target
+            }) {
+                self.preflight.push((*config_check).clone());
+            }
+        }
         if !self.launch_command_modified {
             self.placeholder_launch_command = config.placeholder_launch_command.clone();
         }
@@ -133,7 +702,52 @@ impl GameConfig {
             .map(|command| (*command).clone().into())
             .collect();
 
+        let paired_actions: Vec<crate::ui::SixtyPairedAction> = self
+            .paired_actions
+            .iter()
+            .map(|action| (*action).clone().into())
+            .collect();
+        let overlays: Vec<crate::ui::SixtyFileOverlay> = self
+            .overlays
+            .iter()
+            .map(|overlay| (*overlay).clone().into())
+            .collect();
+        let preflight: Vec<crate::ui::SixtyPreflightCheck> = self
+            .preflight
+            .iter()
+            .map(|check| (*check).clone().into())
+            .collect();
+        let dll_overrides: Vec<crate::ui::SixtyDllOverride> = self
+            .proton
+            .dll_overrides
+            .iter()
+            .map(|dll_override| (*dll_override).clone().into())
+            .collect();
+
         if is_game_config {
+            main_window.set_game_launch_placeholder(sixtyfps::SharedString::from(
+                self.placeholder_launch_command.clone(),
+            ));
+            main_window.set_game_gamescope(self.gamescope.clone().into());
+            main_window.set_game_mangohud(self.mangohud.clone().into());
+            main_window.set_game_render_on(crate::ui::render_on(&self.render_on));
+            main_window.set_game_scheduling(self.scheduling.clone().into());
+            main_window.set_game_snapshots(self.snapshots.clone().into());
+            main_window.set_game_safe_mode(self.safe_mode.clone().into());
+            main_window.set_game_playtime(self.playtime.clone().into());
+            main_window.set_game_paired_actions(sixtyfps::ModelHandle::new(std::rc::Rc::new(
+                sixtyfps::VecModel::from(paired_actions),
+            )));
+            main_window.set_game_overlays(sixtyfps::ModelHandle::new(std::rc::Rc::new(
+                sixtyfps::VecModel::from(overlays),
+            )));
+            main_window.set_game_preflight(sixtyfps::ModelHandle::new(std::rc::Rc::new(
+                sixtyfps::VecModel::from(preflight),
+            )));
+            main_window.set_game_proton(self.proton.clone().into());
+            main_window.set_game_dll_overrides(sixtyfps::ModelHandle::new(std::rc::Rc::new(
+                sixtyfps::VecModel::from(dll_overrides),
+            )));
             main_window.set_game_config_options(sixtyfps::ModelHandle::new(std::rc::Rc::new(
                 sixtyfps::VecModel::from(config_options),
             )));
@@ -144,6 +758,29 @@ impl GameConfig {
                 sixtyfps::VecModel::from(post_exit_commands),
             )));
         } else {
+            main_window.set_global_launch_placeholder(sixtyfps::SharedString::from(
+                self.placeholder_launch_command.clone(),
+            ));
+            main_window.set_global_gamescope(self.gamescope.clone().into());
+            main_window.set_global_mangohud(self.mangohud.clone().into());
+            main_window.set_global_render_on(crate::ui::render_on(&self.render_on));
+            main_window.set_global_scheduling(self.scheduling.clone().into());
+            main_window.set_global_snapshots(self.snapshots.clone().into());
+            main_window.set_global_safe_mode(self.safe_mode.clone().into());
+            main_window.set_global_playtime(self.playtime.clone().into());
+            main_window.set_global_paired_actions(sixtyfps::ModelHandle::new(std::rc::Rc::new(
+                sixtyfps::VecModel::from(paired_actions),
+            )));
+            main_window.set_global_overlays(sixtyfps::ModelHandle::new(std::rc::Rc::new(
+                sixtyfps::VecModel::from(overlays),
+            )));
+            main_window.set_global_preflight(sixtyfps::ModelHandle::new(std::rc::Rc::new(
+                sixtyfps::VecModel::from(preflight),
+            )));
+            main_window.set_global_proton(self.proton.clone().into());
+            main_window.set_global_dll_overrides(sixtyfps::ModelHandle::new(std::rc::Rc::new(
+                sixtyfps::VecModel::from(dll_overrides),
+            )));
             main_window.set_global_config_options(sixtyfps::ModelHandle::new(std::rc::Rc::new(
                 sixtyfps::VecModel::from(config_options),
             )));
@@ -0,0 +1,250 @@
+use serde::{Deserialize, Serialize};
+
+use crate::hooks;
+
+// Placeholder the generated gamescope command line replaces in the launch command
+pub const PLACEHOLDER: &str = "%gamescope%";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum GamescopeFilter {
+    Linear,
+    Nearest,
+    Fsr,
+    Nis,
+    Pixel,
+}
+
+impl Default for GamescopeFilter {
+    fn default() -> Self {
+        GamescopeFilter::Linear
+    }
+}
+
+impl GamescopeFilter {
+    pub fn name(&self) -> &'static str {
+        match self {
+            GamescopeFilter::Linear => "linear",
+            GamescopeFilter::Nearest => "nearest",
+            GamescopeFilter::Fsr => "fsr",
+            GamescopeFilter::Nis => "nis",
+            GamescopeFilter::Pixel => "pixel",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "linear" => Some(GamescopeFilter::Linear),
+            "nearest" => Some(GamescopeFilter::Nearest),
+            "fsr" => Some(GamescopeFilter::Fsr),
+            "nis" => Some(GamescopeFilter::Nis),
+            "pixel" => Some(GamescopeFilter::Pixel),
+            _ => None,
+        }
+    }
+}
+
+// Typed gamescope settings, turned into a command line for the %gamescope% placeholder. Sizes and
+// the refresh rate are left to gamescope when 0
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct GamescopeConfig {
+    pub enabled: bool,
+    // Resolution the game renders at (-w, -h)
+    pub game_width: u32,
+    pub game_height: u32,
+    // Resolution of the gamescope window or output (-W, -H)
+    pub output_width: u32,
+    pub output_height: u32,
+    // Frame rate limit (-r)
+    pub refresh_rate: u32,
+    pub fullscreen: bool,
+    pub filter: GamescopeFilter,
+    // Upscaler sharpness from 0 (sharpest) to 20, only used with FSR and NIS
+    pub sharpness: Option<u32>,
+    pub hdr: bool,
+    // Arguments without a field of their own, passed to gamescope as is
+    pub extra_args: String,
+}
+
+impl GamescopeConfig {
+    // Problems that prevent using these settings for a launch with the specified launch command
+    pub fn validate(&self, launch_command: &str) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        if (self.game_width == 0) != (self.game_height == 0) {
+            problems.push("Set both the game width and height, or neither".to_string());
+        }
+        if (self.output_width == 0) != (self.output_height == 0) {
+            problems.push("Set both the output width and height, or neither".to_string());
+        }
+        if self.refresh_rate > 1000 {
+            problems.push(format!("Refresh rate {} is too high", self.refresh_rate));
+        }
+        if let Some(sharpness) = self.sharpness {
+            if sharpness > 20 {
+                problems.push("Sharpness must be between 0 and 20".to_string());
+            }
+            if self.filter != GamescopeFilter::Fsr && self.filter != GamescopeFilter::Nis {
+                problems.push("Sharpness only applies to the fsr and nis filters".to_string());
+            }
+        }
+        if self.enabled && !launch_command.contains(PLACEHOLDER) {
+            problems.push(format!(
+                "The launch command does not contain {}",
+                PLACEHOLDER
+            ));
+        }
+
+        problems
+    }
+
+    // The gamescope command line, ending with the `--` that separates it from the game command
+    pub fn command_line(&self) -> String {
+        let mut args = vec!["gamescope".to_string()];
+
+        if self.game_width > 0 && self.game_height > 0 {
+            args.push(format!("-w {} -h {}", self.game_width, self.game_height));
+        }
+        if self.output_width > 0 && self.output_height > 0 {
+            args.push(format!(
+                "-W {} -H {}",
+                self.output_width, self.output_height
+            ));
+        }
+        if self.refresh_rate > 0 {
+            args.push(format!("-r {}", self.refresh_rate));
+        }
+        if self.fullscreen {
+            args.push("-f".to_string());
+        }
+        if self.filter != GamescopeFilter::Linear {
+            args.push(format!("-F {}", self.filter.name()));
+        }
+        if let Some(sharpness) = self.sharpness {
+            args.push(format!("--sharpness {}", sharpness));
+        }
+        if self.hdr {
+            args.push("--hdr-enabled".to_string());
+        }
+        if !self.extra_args.trim().is_empty() {
+            args.push(self.extra_args.trim().to_string());
+        }
+        args.push("--".to_string());
+
+        args.join(" ")
+    }
+
+    // Parse a free-form gamescope command line, like the replace_with of an existing option.
+    // Arguments that have no field of their own end up in extra_args
+    pub fn parse(command_line: &str) -> Self {
+        let mut config = Self {
+            enabled: true,
+            ..Default::default()
+        };
+        let mut extra_args: Vec<String> = Vec::new();
+        let mut words = hooks::split_words(command_line).into_iter().peekable();
+
+        if words
+            .peek()
+            .map_or(false, |word| word.ends_with("gamescope"))
+        {
+            words.next();
+        }
+
+        while let Some(word) = words.next() {
+            let mut number = || words.next().and_then(|value| value.parse::<u32>().ok());
+            match word.as_str() {
+                "-w" | "--nested-width" => config.game_width = number().unwrap_or(0),
+                "-h" | "--nested-height" => config.game_height = number().unwrap_or(0),
+                "-W" | "--output-width" => config.output_width = number().unwrap_or(0),
+                "-H" | "--output-height" => config.output_height = number().unwrap_or(0),
+                "-r" | "--nested-refresh" => config.refresh_rate = number().unwrap_or(0),
+                "--sharpness" | "--fsr-sharpness" => config.sharpness = number(),
+                "-f" | "--fullscreen" => config.fullscreen = true,
+                "-U" | "--fsr-upscaling" => config.filter = GamescopeFilter::Fsr,
+                "-Y" | "--nis-upscaling" => config.filter = GamescopeFilter::Nis,
+                "-n" | "--nearest-neighbor-filter" => config.filter = GamescopeFilter::Nearest,
+                "-F" | "--filter" => {
+                    let name = words.next().unwrap_or_default();
+                    match GamescopeFilter::from_name(&name) {
+                        Some(filter) => config.filter = filter,
+                        None => extra_args.extend([word, name]),
+                    }
+                }
+                "--hdr-enabled" => config.hdr = true,
+                // Everything after -- is the game command, which comes from the launch command
+                "--" => break,
+                _ => extra_args.push(word),
+            }
+        }
+
+        config.extra_args = extra_args
+            .iter()
+            .map(|arg| {
+                if arg.contains(char::is_whitespace) {
+                    format!("'{}'", arg)
+                } else {
+                    arg.clone()
+                }
+            })
+            .collect::<Vec<String>>()
+            .join(" ");
+
+        config
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command_line_round_trip() {
+        let config = GamescopeConfig {
+            enabled: true,
+            game_width: 1280,
+            game_height: 720,
+            output_width: 2560,
+            output_height: 1440,
+            refresh_rate: 60,
+            fullscreen: true,
+            filter: GamescopeFilter::Fsr,
+            sharpness: Some(5),
+            hdr: true,
+            extra_args: "--adaptive-sync --cursor '/tmp/my cursor.png'".to_string(),
+        };
+        assert_eq!(
+            config.command_line(),
+            "gamescope -w 1280 -h 720 -W 2560 -H 1440 -r 60 -f -F fsr --sharpness 5 \
+             --hdr-enabled --adaptive-sync --cursor '/tmp/my cursor.png' --"
+        );
+        assert_eq!(GamescopeConfig::parse(&config.command_line()), config);
+
+        let config = GamescopeConfig {
+            enabled: true,
+            ..Default::default()
+        };
+        assert_eq!(config.command_line(), "gamescope --");
+        assert_eq!(GamescopeConfig::parse(&config.command_line()), config);
+    }
+
+    #[test]
+    fn parse_long_and_legacy_options() {
+        let config = GamescopeConfig::parse(
+            "/usr/bin/gamescope --nested-width 1920 --nested-height 1080 -U --fsr-sharpness 2 \
+             --fullscreen -- %command%",
+        );
+        assert_eq!(config.game_width, 1920);
+        assert_eq!(config.game_height, 1080);
+        assert_eq!(config.filter, GamescopeFilter::Fsr);
+        assert_eq!(config.sharpness, Some(2));
+        assert!(config.fullscreen);
+        assert_eq!(config.extra_args, "");
+    }
+
+    #[test]
+    fn parse_keeps_unknown_arguments() {
+        let config = GamescopeConfig::parse("gamescope -F bicubic -e --");
+        assert_eq!(config.filter, GamescopeFilter::Linear);
+        assert_eq!(config.extra_args, "-F bicubic -e");
+    }
+}
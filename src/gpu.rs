@@ -0,0 +1,266 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+// Used when no sysfs root is configured, other roots allow pointing steamrunner at a copy of the
+// relevant sysfs files
+pub const DEFAULT_SYSFS_ROOT: &str = "/sys";
+
+#[derive(Debug, Clone)]
+pub struct Gpu {
+    // Name of the DRM device, like card1
+    pub card: String,
+    // PCI address like 0000:01:00.0, which unlike the card number doesn't change between boots
+    pub pci_slot: String,
+    pub vendor_id: u16,
+    pub device_id: u16,
+    pub driver: String,
+    // The GPU the firmware used during boot, which renders everything by default
+    pub boot_vga: bool,
+}
+
+impl Gpu {
+    pub fn vendor_name(&self) -> &'static str {
+        match self.vendor_id {
+            0x10de => "NVIDIA",
+            0x1002 => "AMD",
+            0x8086 => "Intel",
+            _ => "Unknown vendor",
+        }
+    }
+
+    // Short description of the GPU for the config editor
+    pub fn describe(&self) -> String {
+        format!(
+            "{} {:04x}:{:04x}, {} driver, {}{}",
+            self.vendor_name(),
+            self.vendor_id,
+            self.device_id,
+            self.driver,
+            self.card,
+            if self.boot_vga { " (primary)" } else { "" }
+        )
+    }
+
+    // Environment variables that make OpenGL and Vulkan render on this GPU. The primary GPU is used
+    // by default, so it doesn't need any
+    pub fn env(&self) -> BTreeMap<String, String> {
+        let mut env = BTreeMap::new();
+        if self.boot_vga {
+            return env;
+        }
+
+        let mut set = |name: &str, value: &str| {
+            env.insert(name.to_string(), value.to_string());
+        };
+        if self.driver == "nvidia" {
+            // PRIME render offload of the proprietary driver, the same variables prime-run sets
+            set("__NV_PRIME_RENDER_OFFLOAD", "1");
+            set("__GLX_VENDOR_LIBRARY_NAME", "nvidia");
+            set("__VK_LAYER_NV_optimus", "NVIDIA_only");
+        } else {
+            // Mesa drivers select the device with DRI_PRIME, Vulkan through the device select layer
+            set(
+                "DRI_PRIME",
+                &format!(
+                    "pci-{}",
+                    self.pci_slot.replace(|chr| chr == ':' || chr == '.', "_")
+                ),
+            );
+            set(
+                "MESA_VK_DEVICE_SELECT",
+                &format!("{:04x}:{:04x}", self.vendor_id, self.device_id),
+            );
+        }
+
+        env
+    }
+}
+
+// Find the GPUs in class/drm of the sysfs root, leaving out connectors like card0-HDMI-A-1 and
+// render nodes
+pub fn enumerate(sysfs_root: &str) -> Vec<Gpu> {
+    let sysfs_root = if sysfs_root.is_empty() {
+        DEFAULT_SYSFS_ROOT
+    } else {
+        sysfs_root
+    };
+
+    let mut gpus: Vec<Gpu> = fs::read_dir(Path::new(sysfs_root).join("class/drm"))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let card = entry.file_name().to_string_lossy().to_string();
+            if !card.starts_with("card") || card.contains('-') {
+                return None;
+            }
+
+            let device = entry.path().join("device");
+            let read = |name: &str| {
+                fs::read_to_string(device.join(name))
+                    .map(|value| value.trim().to_string())
+                    .unwrap_or_default()
+            };
+            let id = |name: &str| u16::from_str_radix(read(name).trim_start_matches("0x"), 16).ok();
+            let uevent = read("uevent");
+            let uevent_value = |key: &str| {
+                uevent
+                    .lines()
+                    .find_map(|line| line.strip_prefix(key))
+                    .unwrap_or_default()
+                    .to_string()
+            };
+
+            Some(Gpu {
+                pci_slot: uevent_value("PCI_SLOT_NAME="),
+                driver: uevent_value("DRIVER="),
+                vendor_id: id("vendor")?,
+                device_id: id("device")?,
+                boot_vga: read("boot_vga") == "1",
+                card,
+            })
+        })
+        .collect();
+    gpus.sort_by(|gpu, other| gpu.card.cmp(&other.card));
+
+    gpus
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, path::PathBuf, process};
+
+    // A sysfs root with class/drm laid out like the kernel does, removed again when dropped
+    struct SysfsRoot(PathBuf);
+
+    impl SysfsRoot {
+        fn new(name: &str) -> Self {
+            let root = env::temp_dir().join(format!("steamrunner-gpu-{}-{}", name, process::id()));
+            fs::remove_dir_all(&root).unwrap_or(());
+            fs::create_dir_all(root.join("class/drm")).unwrap();
+            Self(root)
+        }
+
+        fn add_card(
+            &self,
+            card: &str,
+            ids: (&str, &str),
+            slot: &str,
+            driver: &str,
+            boot_vga: bool,
+        ) {
+            let device = self.0.join("class/drm").join(card).join("device");
+            fs::create_dir_all(&device).unwrap();
+            fs::write(device.join("vendor"), format!("{}\n", ids.0)).unwrap();
+            fs::write(device.join("device"), format!("{}\n", ids.1)).unwrap();
+            fs::write(
+                device.join("uevent"),
+                format!(
+                    "DRIVER={}\nPCI_CLASS=30000\nPCI_SLOT_NAME={}\n",
+                    driver, slot
+                ),
+            )
+            .unwrap();
+            fs::write(
+                device.join("boot_vga"),
+                if boot_vga { "1\n" } else { "0\n" },
+            )
+            .unwrap();
+        }
+
+        fn path(&self) -> &str {
+            self.0.to_str().unwrap()
+        }
+    }
+
+    impl Drop for SysfsRoot {
+        fn drop(&mut self) {
+            fs::remove_dir_all(&self.0).unwrap_or(());
+        }
+    }
+
+    #[test]
+    fn enumerates_cards_only() {
+        let root = SysfsRoot::new("enumerate");
+        root.add_card(
+            "card1",
+            ("0x10de", "0x2520"),
+            "0000:01:00.0",
+            "nvidia",
+            false,
+        );
+        root.add_card("card0", ("0x8086", "0x9a49"), "0000:00:02.0", "i915", true);
+        // Connectors and render nodes live next to the cards
+        fs::create_dir_all(root.0.join("class/drm/card0-eDP-1")).unwrap();
+        fs::create_dir_all(root.0.join("class/drm/renderD128/device")).unwrap();
+
+        let gpus = enumerate(root.path());
+        assert_eq!(gpus.len(), 2);
+
+        assert_eq!(gpus[0].card, "card0");
+        assert_eq!(gpus[0].pci_slot, "0000:00:02.0");
+        assert_eq!(gpus[0].vendor_id, 0x8086);
+        assert_eq!(gpus[0].device_id, 0x9a49);
+        assert_eq!(gpus[0].driver, "i915");
+        assert!(gpus[0].boot_vga);
+
+        assert_eq!(gpus[1].card, "card1");
+        assert_eq!(gpus[1].vendor_name(), "NVIDIA");
+        assert!(!gpus[1].boot_vga);
+    }
+
+    #[test]
+    fn skips_cards_without_ids() {
+        let root = SysfsRoot::new("no-ids");
+        root.add_card(
+            "card0",
+            ("0x1002", "0x73bf"),
+            "0000:03:00.0",
+            "amdgpu",
+            true,
+        );
+        fs::remove_file(root.0.join("class/drm/card0/device/vendor")).unwrap();
+
+        assert!(enumerate(root.path()).is_empty());
+    }
+
+    #[test]
+    fn nvidia_uses_prime_render_offload() {
+        let root = SysfsRoot::new("nvidia");
+        root.add_card("card0", ("0x8086", "0x9a49"), "0000:00:02.0", "i915", true);
+        root.add_card(
+            "card1",
+            ("0x10de", "0x2520"),
+            "0000:01:00.0",
+            "nvidia",
+            false,
+        );
+        let gpus = enumerate(root.path());
+
+        assert!(gpus[0].env().is_empty());
+        let env = gpus[1].env();
+        assert_eq!(env.len(), 3);
+        assert_eq!(env["__NV_PRIME_RENDER_OFFLOAD"], "1");
+        assert_eq!(env["__GLX_VENDOR_LIBRARY_NAME"], "nvidia");
+        assert_eq!(env["__VK_LAYER_NV_optimus"], "NVIDIA_only");
+    }
+
+    #[test]
+    fn mesa_uses_dri_prime() {
+        let root = SysfsRoot::new("mesa");
+        root.add_card("card0", ("0x8086", "0x9a49"), "0000:00:02.0", "i915", true);
+        root.add_card(
+            "card1",
+            ("0x1002", "0x73bf"),
+            "0000:03:00.0",
+            "amdgpu",
+            false,
+        );
+        let gpus = enumerate(root.path());
+
+        let env = gpus[1].env();
+        assert_eq!(env.len(), 2);
+        assert_eq!(env["DRI_PRIME"], "pci-0000_03_00_0");
+        assert_eq!(env["MESA_VK_DEVICE_SELECT"], "1002:73bf");
+    }
+}
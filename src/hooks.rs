@@ -0,0 +1,510 @@
+use std::{
+    env, io,
+    process::{Child, Command},
+    thread,
+    time::{Duration, Instant},
+};
+
+use notify_rust::Notification;
+
+use crate::{
+    frequency,
+    game_config::{ConfigCommand, PairedAction, Shell},
+    session_log::SessionLog,
+    shared,
+};
+
+// Variables the Steam runtime and overlay add to the environment of everything started by a game
+const STEAM_ENV_VARS: [&str; 6] = [
+    "LD_PRELOAD",
+    "STEAM_RUNTIME",
+    "STEAM_RUNTIME_LIBRARY_PATH",
+    "SYSTEM_LD_LIBRARY_PATH",
+    "SYSTEM_PATH",
+    "PRESSURE_VESSEL_RUNTIME",
+];
+
+// Run all enabled commands of one phase (pre-launch or post-exit) that are due according to their
+// frequency, and return once all of them have exited. Commands run one after another by default,
+// parallel commands are started as soon as the commands they depend on have finished.
+pub fn run_commands(commands: &[ConfigCommand], phase: &str, state_dir: &str, log: &SessionLog) {
+    let commands: Vec<&ConfigCommand> = commands
+        .iter()
+        .filter(|command| command.enabled)
+        .filter(|command| {
+            let due = frequency::is_due(command, state_dir);
+            if !due {
+                log.log(&format!(
+                    "Skipping {} command {}, it runs {} and already ran",
+                    phase,
+                    label(command),
+                    command.frequency.name()
+                ));
+            }
+            due
+        })
+        .collect();
+    if commands.is_empty() {
+        return;
+    }
+
+    let mut dependencies = build_dependencies(&commands, phase, log);
+
+    // Fall back to running everything in order if the dependencies can't be satisfied, running
+    // the commands in the wrong order is better than not running them at all
+    if let Some(cycle) = find_cycle(&dependencies) {
+        let names: Vec<String> = cycle.iter().map(|index| label(commands[*index])).collect();
+        log.log(&format!(
+            "Dependency cycle between {} commands: {}, running them in order instead",
+            phase,
+            names.join(", ")
+        ));
+        Notification::new()
+            .summary(&format!("Dependency cycle in {} commands", phase))
+            .body(&format!(
+                "The commands {} depend on each other, running them in order instead",
+                names.join(", ")
+            ))
+            .icon("notification_error")
+            .show()
+            .unwrap();
+        dependencies = (0..commands.len())
+            .map(|index| (0..index).collect())
+            .collect();
+    }
+
+    let phase_start = Instant::now();
+    let mut started = vec![false; commands.len()];
+    let mut finished = vec![false; commands.len()];
+    let mut running: Vec<(usize, Child, Instant)> = Vec::new();
+
+    while finished.iter().any(|finished| !finished) {
+        // Start every command whose dependencies have finished. Commands that fail to start count
+        // as finished right away, so keep going until nothing new can be started
+        let mut started_any = true;
+        while started_any {
+            started_any = false;
+            for index in 0..commands.len() {
+                if started[index] || !dependencies[index].iter().all(|dep| finished[*dep]) {
+                    continue;
+                }
+                started[index] = true;
+                started_any = true;
+
+                let command = commands[index];
+                log.log(&format!("Starting {} command {}", phase, label(command)));
+                match build_command(command).and_then(|mut process| process.spawn()) {
+                    Ok(handle) => running.push((index, handle, Instant::now())),
+                    Err(why) => {
+                        log.log(&format!(
+                            "Failed to run {} command {}: {}",
+                            phase,
+                            label(command),
+                            why
+                        ));
+                        Notification::new()
+                            .summary(&format!("Failed to run {} command", phase))
+                            .body(&format!(
+                                "Failed to run command {}: {}",
+                                &command.command, why
+                            ))
+                            .icon("notification_error")
+                            .show()
+                            .unwrap();
+                        finished[index] = true;
+                    }
+                }
+            }
+        }
+
+        if running.is_empty() {
+            break;
+        }
+
+        // Poll the running commands instead of waiting on one of them, so that a quick command
+        // can unblock its dependents while a slow one is still running
+        let mut exited_any = false;
+        running.retain_mut(|(index, handle, start)| {
+            let status = match handle.try_wait() {
+                Ok(None) => return true,
+                Ok(Some(status)) => {
+                    if status.success() {
+                        frequency::mark_ran(commands[*index], state_dir);
+                    }
+                    status.to_string()
+                }
+                Err(why) => format!("failed to wait: {}", why),
+            };
+            log.log(&format!(
+                "Finished {} command {} in {:.3}s ({})",
+                phase,
+                label(commands[*index]),
+                start.elapsed().as_secs_f64(),
+                status
+            ));
+            finished[*index] = true;
+            exited_any = true;
+            false
+        });
+
+        if !exited_any {
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    log.log(&format!(
+        "All {} commands finished in {:.3}s",
+        phase,
+        phase_start.elapsed().as_secs_f64()
+    ));
+}
+
+// Run the setup commands of the enabled paired actions one after another. Returns the actions whose
+// setup command succeeded, which are the ones that need to be undone later
+pub fn run_setups(actions: &[PairedAction], log: &SessionLog) -> Vec<PairedAction> {
+    let mut succeeded = Vec::new();
+
+    for action in actions.iter().filter(|action| action.enabled) {
+        let setup = || {
+            run_to_completion(
+                action,
+                &action.setup,
+                &format!("setup of {}", action.name),
+                log,
+            )
+        };
+        let set_up = if action.shared {
+            shared::acquire(&action.name, setup, log)
+        } else {
+            setup()
+        };
+        if set_up {
+            succeeded.push(action.clone());
+        } else if let Err(why) = Notification::new()
+            .summary("Paired action setup failed")
+            .body(&format!(
+                "The setup command of {} failed, it will not be undone",
+                action.name
+            ))
+            .icon("notification_error")
+            .show()
+        {
+            // The actions that were already set up still have to be undone
+            log.log(&format!("Failed to show notification: {}", why));
+        }
+    }
+
+    succeeded
+}
+
+// Undo paired actions in the reverse order of their setup. Shared actions are only undone when no
+// other session holds them anymore
+pub fn run_undos(actions: &[PairedAction], log: &SessionLog) {
+    for action in actions.iter().rev() {
+        let undo = || {
+            run_to_completion(
+                action,
+                &action.undo,
+                &format!("undo of {}", action.name),
+                log,
+            );
+        };
+        if action.shared {
+            shared::release(&action.name, undo, log);
+        } else {
+            undo();
+        }
+    }
+}
+
+// Run the setup or undo command of a paired action with the shell, working directory and environment
+// of the action and wait for it, returning whether it exited successfully
+fn run_to_completion(
+    action: &PairedAction,
+    command: &str,
+    description: &str,
+    log: &SessionLog,
+) -> bool {
+    let start = Instant::now();
+    log.log(&format!("Starting {}", description));
+
+    let command = ConfigCommand {
+        command: command.to_string(),
+        shell: action.shell,
+        working_dir: action.working_dir.clone(),
+        env: action.env.clone(),
+        strip_steam_env: action.strip_steam_env,
+        ..Default::default()
+    };
+    match build_command(&command).and_then(|mut process| process.status()) {
+        Ok(status) => {
+            log.log(&format!(
+                "Finished {} in {:.3}s ({})",
+                description,
+                start.elapsed().as_secs_f64(),
+                status
+            ));
+            status.success()
+        }
+        Err(why) => {
+            log.log(&format!("Failed to run {}: {}", description, why));
+            false
+        }
+    }
+}
+
+// Create the process for a command with its own shell, working directory and environment
+fn build_command(command: &ConfigCommand) -> io::Result<Command> {
+    let mut process = match command.shell {
+        Shell::Sh | Shell::Bash | Shell::Fish => {
+            let shell = match command.shell {
+                Shell::Bash => "bash",
+                Shell::Fish => "fish",
+                _ => "sh",
+            };
+            let mut process = Command::new(shell);
+            process.arg("-c").arg(&command.command);
+            process
+        }
+        Shell::Exec => {
+            let words = split_words(&command.command);
+            if words.is_empty() {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "Empty command"));
+            }
+            let mut process = Command::new(&words[0]);
+            process.args(&words[1..]);
+            process
+        }
+    };
+
+    if command.strip_steam_env {
+        // The runtime keeps the host values of the variables it changes in SYSTEM_*
+        match env::var("SYSTEM_LD_LIBRARY_PATH") {
+            Ok(library_path) => process.env("LD_LIBRARY_PATH", library_path),
+            Err(_) => process.env_remove("LD_LIBRARY_PATH"),
+        };
+        if let Ok(path) = env::var("SYSTEM_PATH") {
+            process.env("PATH", path);
+        }
+        for var in STEAM_ENV_VARS.iter() {
+            process.env_remove(var);
+        }
+    }
+
+    if !command.working_dir.is_empty() {
+        process.current_dir(&command.working_dir);
+    }
+    process.envs(&command.env);
+
+    Ok(process)
+}
+
+// Split a command into words the way a shell would, honoring quotes and backslash escapes
+pub fn split_words(command: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote: Option<char> = None;
+    let mut chars = command.chars();
+
+    while let Some(chr) = chars.next() {
+        match (quote, chr) {
+            (Some(open), chr) if chr == open => quote = None,
+            (Some('"'), '\\') | (None, '\\') => {
+                if let Some(escaped) = chars.next() {
+                    word.push(escaped);
+                }
+                in_word = true;
+            }
+            (Some(_), chr) => word.push(chr),
+            (None, '\'') | (None, '"') => {
+                quote = Some(chr);
+                in_word = true;
+            }
+            (None, chr) if chr.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            (None, chr) => {
+                word.push(chr);
+                in_word = true;
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+
+    words
+}
+
+// Name used for a command in the log and notifications
+fn label(command: &ConfigCommand) -> String {
+    if command.name.is_empty() {
+        format!("`{}`", command.command)
+    } else {
+        format!("{} (`{}`)", command.name, command.command)
+    }
+}
+
+// Find the indices of the commands each command has to wait for. A command that isn't parallel
+// waits for every command before it, and every command after it waits for it. On top of that
+// every command waits for the commands it names in `after`.
+fn build_dependencies(
+    commands: &[&ConfigCommand],
+    phase: &str,
+    log: &SessionLog,
+) -> Vec<Vec<usize>> {
+    let mut dependencies: Vec<Vec<usize>> = vec![Vec::new(); commands.len()];
+    let mut last_sequential: Option<usize> = None;
+
+    for (index, command) in commands.iter().enumerate() {
+        if command.parallel {
+            if let Some(last_sequential) = last_sequential {
+                dependencies[index].push(last_sequential);
+            }
+        } else {
+            dependencies[index].extend(0..index);
+            last_sequential = Some(index);
+        }
+
+        for name in &command.after {
+            match commands
+                .iter()
+                .position(|other| !other.name.is_empty() && &other.name == name)
+            {
+                Some(dependency) => {
+                    if !dependencies[index].contains(&dependency) {
+                        dependencies[index].push(dependency);
+                    }
+                }
+                None => log.log(&format!(
+                    "Ignoring dependency {} of {} command {}, no enabled {} command has that name",
+                    name,
+                    phase,
+                    label(command),
+                    phase
+                )),
+            }
+        }
+    }
+
+    dependencies
+}
+
+// Topologically sort the dependency graph, returning the commands that could not be sorted
+// because they are part of (or depend on) a cycle
+fn find_cycle(dependencies: &[Vec<usize>]) -> Option<Vec<usize>> {
+    let mut sorted = vec![false; dependencies.len()];
+    let mut progress = true;
+
+    while progress {
+        progress = false;
+        for index in 0..dependencies.len() {
+            if !sorted[index] && dependencies[index].iter().all(|dep| sorted[*dep]) {
+                sorted[index] = true;
+                progress = true;
+            }
+        }
+    }
+
+    let unsorted: Vec<usize> = (0..dependencies.len())
+        .filter(|index| !sorted[*index])
+        .collect();
+
+    if unsorted.is_empty() {
+        None
+    } else {
+        Some(unsorted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(name: &str, parallel: bool, after: &[&str]) -> ConfigCommand {
+        ConfigCommand {
+            command: format!("echo {}", name),
+            enabled: true,
+            name: name.to_string(),
+            parallel,
+            after: after.iter().map(|name| name.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn dependencies(commands: &[ConfigCommand]) -> Vec<Vec<usize>> {
+        let commands: Vec<&ConfigCommand> = commands.iter().collect();
+        let mut dependencies = build_dependencies(
+            &commands,
+            "test",
+            &SessionLog::new(&"/dev/null".to_string()),
+        );
+        for dependency in &mut dependencies {
+            dependency.sort_unstable();
+        }
+        dependencies
+    }
+
+    #[test]
+    fn split_words_like_a_shell() {
+        assert_eq!(split_words(""), Vec::<String>::new());
+        assert_eq!(split_words("  a   b\tc "), ["a", "b", "c"]);
+        assert_eq!(split_words("a 'b c' \"d e\""), ["a", "b c", "d e"]);
+        assert_eq!(split_words("a\\ b c"), ["a b", "c"]);
+        assert_eq!(split_words("pre'quoted'post"), ["prequotedpost"]);
+        assert_eq!(split_words("'' \"\""), ["", ""]);
+        // Backslashes only escape outside of single quotes
+        assert_eq!(split_words("'a\\b' \"c\\\"d\""), ["a\\b", "c\"d"]);
+        // Nothing is expanded
+        assert_eq!(split_words("$HOME ~ *"), ["$HOME", "~", "*"]);
+    }
+
+    #[test]
+    fn sequential_commands_wait_for_the_ones_before() {
+        let commands = [
+            command("a", false, &[]),
+            command("b", false, &[]),
+            command("c", false, &[]),
+        ];
+        assert_eq!(dependencies(&commands), [vec![], vec![0], vec![0, 1]]);
+    }
+
+    #[test]
+    fn parallel_commands_wait_for_the_last_sequential_one() {
+        let commands = [
+            command("a", false, &[]),
+            command("b", true, &[]),
+            command("c", true, &["b"]),
+            command("d", false, &[]),
+            command("e", true, &["missing"]),
+        ];
+        assert_eq!(
+            dependencies(&commands),
+            [vec![], vec![0], vec![0, 1], vec![0, 1, 2], vec![3]]
+        );
+        assert_eq!(find_cycle(&dependencies(&commands)), None);
+    }
+
+    #[test]
+    fn cycles() {
+        let commands = [
+            command("a", true, &[]),
+            command("b", true, &["c"]),
+            command("c", true, &["b"]),
+            command("d", true, &["c"]),
+        ];
+        // The commands in the cycle and the ones waiting for it can't be sorted
+        assert_eq!(find_cycle(&dependencies(&commands)), Some(vec![1, 2, 3]));
+
+        let commands = [command("a", true, &["a"])];
+        assert_eq!(find_cycle(&dependencies(&commands)), Some(vec![0]));
+
+        // A sequential command waits for everything before it, so naming a later one is a cycle
+        let commands = [command("a", false, &["b"]), command("b", false, &[])];
+        assert_eq!(find_cycle(&dependencies(&commands)), Some(vec![0, 1]));
+    }
+}
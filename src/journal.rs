@@ -0,0 +1,200 @@
+use std::{
+    fs::{self, File},
+    io::Write,
+    path::Path,
+    process,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use notify_rust::Notification;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    game_config::{ConfigCommand, InterruptedSessionAction, PairedAction},
+    hooks,
+    session_log::SessionLog,
+};
+
+// Record of a running session, written to journal/<appid>_<pid>.yaml in the state directory when
+// the game is launched and removed once the post-exit commands have run. A journal left behind by
+// a steamrunner process that no longer exists means that session was interrupted.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Journal {
+    pub appid: String,
+    pub pid: u32,
+    pub started: u64,
+    pub post_exit_commands: Vec<ConfigCommand>,
+    // Paired actions whose setup succeeded, added once they have run
+    #[serde(default)]
+    pub undo_actions: Vec<PairedAction>,
+    #[serde(skip)]
+    path: String,
+}
+
+impl Journal {
+    pub fn create(
+        state_dir: &String,
+        appid: &String,
+        post_exit_commands: &[ConfigCommand],
+    ) -> Self {
+        let journal_dir = format!("{}/journal", state_dir);
+        fs::create_dir_all(&journal_dir).unwrap_or(());
+
+        let journal = Self {
+            appid: appid.clone(),
+            pid: process::id(),
+            started: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_secs())
+                .unwrap_or(0),
+            post_exit_commands: post_exit_commands
+                .iter()
+                .filter(|command| command.enabled)
+                .cloned()
+                .collect(),
+            undo_actions: Vec::new(),
+            path: format!("{}/{}_{}.yaml", journal_dir, appid, process::id()),
+        };
+        journal.write();
+
+        journal
+    }
+
+    // Record the paired actions that need to be undone if the session is interrupted
+    pub fn set_undo_actions(&mut self, actions: &[PairedAction]) {
+        self.undo_actions = actions.to_vec();
+        self.write();
+    }
+
+    fn write(&self) {
+        match File::create(&self.path) {
+            Ok(mut file) => file
+                .write_all(serde_yaml::to_string(self).unwrap().as_bytes())
+                .unwrap_or(()),
+            Err(why) => println!("Failed to write session journal {}: {}", self.path, why),
+        }
+    }
+
+    // Remove the journal after the session has finished cleanly
+    pub fn remove(&self) {
+        fs::remove_file(&self.path).unwrap_or(());
+    }
+
+    // Find the journals left behind by steamrunner processes that are no longer running
+    pub fn find_interrupted(state_dir: &String) -> Vec<Self> {
+        let mut interrupted = Vec::new();
+
+        for entry in fs::read_dir(format!("{}/journal", state_dir))
+            .into_iter()
+            .flatten()
+            .flatten()
+        {
+            let path = entry.path().to_string_lossy().to_string();
+            let mut journal: Journal = match File::open(&path)
+                .ok()
+                .and_then(|file| serde_yaml::from_reader(file).ok())
+            {
+                Some(journal) => journal,
+                None => {
+                    println!("Ignoring unreadable session journal {}", path);
+                    continue;
+                }
+            };
+            journal.path = path;
+
+            if !is_running(journal.pid) {
+                interrupted.push(journal);
+            }
+        }
+
+        interrupted
+    }
+
+    // Undo the paired actions and run the post-exit commands the interrupted session never got to,
+    // and forget the session
+    pub fn recover(&self, log: &SessionLog) {
+        log.log(&format!(
+            "Running the post-exit commands of the interrupted session of {} (pid {}, started at {})",
+            self.appid, self.pid, self.started
+        ));
+        hooks::run_undos(&self.undo_actions, log);
+        // The journal is kept in journal/ of the state dir
+        let state_dir = Path::new(&self.path)
+            .parent()
+            .and_then(Path::parent)
+            .map(|state_dir| state_dir.to_string_lossy().to_string())
+            .unwrap_or_default();
+        hooks::run_commands(&self.post_exit_commands, "post-exit", &state_dir, log);
+        self.remove();
+    }
+}
+
+// Handle the sessions found to be interrupted in the way set in the global config. Returns the
+// sessions that should be asked about on the Start Menu
+pub fn handle_interrupted(
+    interrupted: Vec<Journal>,
+    action: InterruptedSessionAction,
+    gui: bool,
+    log: &SessionLog,
+) -> Vec<Journal> {
+    match action {
+        InterruptedSessionAction::Run => {
+            for journal in &interrupted {
+                journal.recover(log);
+            }
+            Vec::new()
+        }
+        InterruptedSessionAction::Discard => {
+            for journal in &interrupted {
+                log.log(&format!(
+                    "Discarding the interrupted session of {} (pid {})",
+                    journal.appid, journal.pid
+                ));
+                journal.remove();
+            }
+            Vec::new()
+        }
+        InterruptedSessionAction::Ask if gui => interrupted,
+        InterruptedSessionAction::Ask => {
+            for journal in &interrupted {
+                ask_with_notification(journal, log);
+            }
+            Vec::new()
+        }
+    }
+}
+
+// Without the gui the only way to ask is a notification with actions. Closing the notification
+// keeps the journal, so the question is asked again on the next launch
+fn ask_with_notification(journal: &Journal, log: &SessionLog) {
+    let handle = match Notification::new()
+        .summary("Interrupted session found")
+        .body(&format!(
+            "The session of {} was interrupted before its post-exit commands were run",
+            journal.appid
+        ))
+        .action("run", "Run them now")
+        .action("discard", "Discard")
+        .show()
+    {
+        Ok(handle) => handle,
+        Err(why) => {
+            println!("Failed to show notification: {}", why);
+            return;
+        }
+    };
+
+    handle.wait_for_action(|action| match action {
+        "run" => journal.recover(log),
+        "discard" => journal.remove(),
+        _ => (),
+    });
+}
+
+// Check whether the pid still belongs to a steamrunner process, as pids are reused after reboots
+pub fn is_running(pid: u32) -> bool {
+    match fs::read_to_string(Path::new("/proc").join(pid.to_string()).join("comm")) {
+        Ok(name) => name.trim() == "steamrunner",
+        Err(_) => false,
+    }
+}
@@ -1,16 +1,44 @@
+mod catalogue;
+mod condition;
+mod frequency;
 mod game_config;
+mod gamescope;
+mod gpu;
+mod hooks;
+mod journal;
+mod mangohud;
+mod overlay;
+mod playtime;
+mod preflight;
+mod process;
+mod proton;
+mod scheduling;
+mod session_log;
+mod shared;
+mod signals;
+mod snapshot;
+mod steam;
+mod time;
+mod tools;
 mod ui;
 
-use clap::{App, Arg};
-use game_config::{ConfigOption, GameConfig};
+use clap::{App, AppSettings, Arg, SubCommand};
+use game_config::{ConfigOption, GameConfig, MissingToolAction};
+use journal::Journal;
 use notify_rust::Notification;
+use overlay::Manifest;
+use process::GameProcess;
+use session_log::SessionLog;
 use std::fs::File;
-use std::process::{Child, Stdio};
+use std::process::Stdio;
 use std::{
+    collections::BTreeMap,
     env, fs,
+    os::unix::process::CommandExt,
     path::Path,
     process::{exit, Command},
     sync::{Arc, Mutex},
+    time::{Duration, Instant},
 };
 
 // The game and global config variables need to be defined like this to allow their usage in the ui
@@ -43,8 +71,50 @@ fn main() {
              .help("Write game output to a log file")
              .long("log")
              .takes_value(false))
+        .arg(Arg::with_name("safemode")
+             .help("Launch with the tweaks selected in the safe mode settings turned off")
+             .long("safe-mode")
+             .takes_value(false))
+        .setting(AppSettings::SubcommandsNegateReqs)
+        .subcommand(SubCommand::with_name("restore")
+             .about("Restore a snapshot of the directories of a game, or list its snapshots")
+             .arg(Arg::with_name("appid").required(true).takes_value(true))
+             .arg(Arg::with_name("snapshot").takes_value(true)))
+        .subcommand(SubCommand::with_name("enable")
+             .about("Enable an option or command of a game for the next launches only")
+             .arg(Arg::with_name("appid").required(true).takes_value(true))
+             .arg(Arg::with_name("item")
+                  .help("The placeholder of an option, or the name or command line of a command")
+                  .required(true)
+                  .takes_value(true))
+             .arg(Arg::with_name("launches")
+                  .help("Number of launches, 1 by default and 0 to stop")
+                  .takes_value(true)))
         .get_matches();
 
+    // Get the config directory using environmental variables, and falling back to a standard path
+    // if the environmental variables do not exist. $HOME is although required
+    let config_dir = env::var("XDG_CONFIG_HOME")
+        .unwrap_or(format!("{}/.config/steamrunner", env::var("HOME").unwrap()));
+
+    if let Some(restore) = matches.subcommand_matches("restore") {
+        restore_snapshot(
+            &config_dir,
+            restore.value_of("appid").unwrap(),
+            restore.value_of("snapshot"),
+        );
+        return;
+    }
+    if let Some(enable) = matches.subcommand_matches("enable") {
+        enable_for_next_launches(
+            &config_dir,
+            enable.value_of("appid").unwrap(),
+            enable.value_of("item").unwrap(),
+            enable.value_of("launches"),
+        );
+        return;
+    }
+
     let command = matches.value_of("command").unwrap();
 
     // Parse the initial command variable supplied by steam to find the steam AppId, which is used
@@ -93,16 +163,18 @@ fn main() {
         )
     };
 
-    // Get the config directory using environmental variables, and falling back to a standard path
-    // if the environmental variables do not exist. $HOME is although required
-    let config_dir = env::var("XDG_CONFIG_HOME")
-        .unwrap_or(format!("{}/.config/steamrunner", env::var("HOME").unwrap()));
-
     // Make sure the config dir for steamlauncher exists, if not create the directories
     if !Path::new(&config_dir).exists() {
         create_config_dirs(&config_dir);
     }
 
+    // The state dir holds data steamrunner keeps track of itself, like the session journals
+    let state_dir = match env::var("XDG_STATE_HOME") {
+        Ok(state_home) => format!("{}/steamrunner", state_home),
+        Err(_) => format!("{}/.local/state/steamrunner", env::var("HOME").unwrap()),
+    };
+    fs::create_dir_all(&state_dir).unwrap_or(());
+
     // Load global config from the file if it exists, and fall back to a statically defined version
     // if the file does not exist
     *GLOBAL_CONFIG.lock().unwrap() =
@@ -111,7 +183,7 @@ fn main() {
         } else {
             let global_config = GameConfig {
                 appid: "".to_string(),
-                placeholder_launch_command: "%mh% %ov% %og% %command%".to_string(),
+                placeholder_launch_command: "%gamescope% %mh% %ov% %og% %command%".to_string(),
                 launch_command_modified: false,
                 placeholder_map: vec![
                     ConfigOption::new(&"%mh%".to_string(), &"mangohud".to_string(), false, false),
@@ -130,6 +202,7 @@ fn main() {
                 ],
                 pre_launch_commands: Vec::new(),
                 post_exit_commands: Vec::new(),
+                ..Default::default()
             };
 
             // Save the newly created config file
@@ -147,92 +220,535 @@ fn main() {
             game_config.appid = appid.clone();
             create_new_game_config(&config_dir, &game_config, &game_config.appid)
         };
+
+    let session_log = Arc::new(SessionLog::new(&format!(
+        "{}/logs/{}_session.log",
+        config_dir, appid
+    )));
+
+    // Tweaks made for one build of the game can stop working when Steam updates it
+    let game_update = steam::detect_update(
+        &appid,
+        &mut GAME_CONFIG.lock().unwrap(),
+        &format!("{}/game_configs/{}.yaml", config_dir, appid),
+        &session_log,
+    );
+
+    // Native titles have no use for the Proton settings, so they are hidden and not applied
+    let is_proton = proton::is_proton_command(command);
+    session_log.log(&format!(
+        "{} title",
+        if is_proton { "Proton" } else { "Native" }
+    ));
+
+    // Overlays of a crashed session would otherwise stay in the game directory for good
+    Manifest::revert_leftover(&state_dir, &session_log);
+    playtime::collect_leftover(&state_dir);
+
+    // Sessions that were killed or crashed mid-game never ran their post-exit commands
+    let interrupted_sessions = journal::handle_interrupted(
+        Journal::find_interrupted(&state_dir),
+        GLOBAL_CONFIG.lock().unwrap().interrupted_sessions,
+        !matches.is_present("nogui"),
+        &session_log,
+    );
+
+    // The free space check and empty paths of the preflight checks refer to the install dir
+    let install_dir = steam::install_dir(&appid);
+
+    let gpus = gpu::enumerate(&GLOBAL_CONFIG.lock().unwrap().sysfs_root);
+
+    let mut safe_mode = matches.is_present("safemode");
     if !matches.is_present("nogui") {
-        if ui::run(&GLOBAL_CONFIG, &GAME_CONFIG, &banner_path, &logo_path) {
-            exit(1);
+        match ui::run(
+            &GLOBAL_CONFIG,
+            &GAME_CONFIG,
+            &banner_path,
+            &logo_path,
+            &state_dir,
+            install_dir.clone(),
+            playtime::describe_remaining(&playtime_remaining(&state_dir, &appid)),
+            interrupted_sessions,
+            game_update,
+            session_log.clone(),
+            catalogue::load(&config_dir),
+            is_proton,
+            gpus.clone(),
+        ) {
+            ui::Launch::Cancelled => exit(1),
+            ui::Launch::Normal => (),
+            ui::Launch::SafeMode => safe_mode = true,
         }
+    } else if let Some(update) = &game_update {
+        safe_mode = safe_mode || steam::ask_safe_mode(update, &appid);
     }
 
-    let game_config = GAME_CONFIG.lock().unwrap();
+    // Playing outside the allowed hours or over the daily playtime is refused before anything runs
+    if let Err(why) = playtime_remaining(&state_dir, &appid) {
+        session_log.log(&format!("Not starting the game: {}", why));
+        Notification::new()
+            .summary("Playtime limit")
+            .body(&format!("The game was not started. {}", why))
+            .icon("notification_error")
+            .show()
+            .unwrap();
+        exit(1);
+    }
 
-    // Run all pre launch commands and wait for them to exit before starting game.
-    for command in &game_config.pre_launch_commands {
-        if command.enabled {
-            let mut handle = match Command::new("sh").arg("-c").arg(&command.command).spawn() {
-                Ok(handle) => handle,
-                Err(why) => {
-                    Notification::new()
-                        .summary("Failed to run pre-launch command")
-                        .body(&format!(
-                            "Failed to run command {}: {}",
-                            &command.command, why
-                        ))
-                        .icon("notification_error")
-                        .show()
-                        .unwrap();
-                    continue;
+    // The config used for this launch, with the options and commands whose conditions don't hold
+    // on this machine right now disabled
+    let mut game_config = GAME_CONFIG.lock().unwrap().clone();
+    game_config.apply_next_launches(&session_log);
+    game_config.apply_conditions(&session_log);
+    if safe_mode {
+        game_config.apply_safe_mode(&session_log);
+    }
+
+    // Invalid gamescope settings would only show up as gamescope failing to start the game
+    let gamescope_problems = game_config
+        .gamescope
+        .validate(&game_config.placeholder_launch_command);
+    if game_config.gamescope.enabled && !gamescope_problems.is_empty() {
+        session_log.log(&format!(
+            "Invalid gamescope settings, not using gamescope: {}",
+            gamescope_problems.join(", ")
+        ));
+        Notification::new()
+            .summary("Invalid gamescope settings")
+            .body(&format!(
+                "{}. Gamescope is disabled for this launch",
+                gamescope_problems.join(". ")
+            ))
+            .icon("notification_error")
+            .show()
+            .unwrap();
+        game_config.gamescope.enabled = false;
+    }
+
+    // MangoHud settings missing from the game config come from the global config
+    game_config.mangohud = game_config
+        .mangohud
+        .with_defaults(&GLOBAL_CONFIG.lock().unwrap().mangohud);
+    let mangohud_problems = game_config.mangohud.validate();
+    if game_config.mangohud.enabled == Some(true) && !mangohud_problems.is_empty() {
+        session_log.log(&format!(
+            "Invalid MangoHud settings, not setting MANGOHUD_CONFIG: {}",
+            mangohud_problems.join(", ")
+        ));
+        Notification::new()
+            .summary("Invalid MangoHud settings")
+            .body(&format!(
+                "{}. The MangoHud settings are not used for this launch",
+                mangohud_problems.join(". ")
+            ))
+            .icon("notification_error")
+            .show()
+            .unwrap();
+        game_config.mangohud.enabled = Some(false);
+    }
+
+    // Some DXVK options only exist in dxvk.conf, which is generated in the config dir so the game
+    // directory is left untouched
+    if is_proton {
+        if let Some(dxvk_config) = game_config.proton.dxvk_config() {
+            let dxvk_config_path = format!("{}/dxvk/{}.conf", config_dir, appid);
+            match fs::create_dir_all(format!("{}/dxvk", config_dir))
+                .and_then(|_| fs::write(&dxvk_config_path, &dxvk_config))
+            {
+                Ok(()) => {
+                    session_log.log(&format!(
+                        "Generated {}:\n{}",
+                        dxvk_config_path,
+                        dxvk_config.trim_end()
+                    ));
+                    game_config.proton.dxvk_config_file = Some(dxvk_config_path);
                 }
-            };
-            handle.wait().unwrap();
+                Err(why) => session_log.log(&format!(
+                    "Failed to write {}, DXVK_CONFIG_FILE is not set: {}",
+                    dxvk_config_path, why
+                )),
+            }
+        }
+        // VKD3D-Proton is only configured through VKD3D_CONFIG, it is logged for the same reason
+        if !game_config.proton.vkd3d_config.trim().is_empty() {
+            session_log.log(&format!(
+                "VKD3D_CONFIG={}",
+                game_config.proton.vkd3d_config.trim()
+            ));
         }
     }
 
-    // Start the game and wait until it exits
-    let handle: Option<Child> = match Command::new("sh")
-        .arg("-c")
-        .arg(game_config.get_launch_command(&command.to_string()))
-        .stdout(if matches.is_present("log") {
-            Stdio::from(
-                File::create(format!("{}/logs/{}_stdout.log", &config_dir, &appid)).unwrap(),
-            )
-        } else {
-            Stdio::null()
-        })
-        .stderr(if matches.is_present("log") {
-            Stdio::from(
-                File::create(format!("{}/logs/{}_stderr.log", &config_dir, &appid)).unwrap(),
-            )
-        } else {
-            Stdio::null()
-        })
-        .spawn()
-    {
-        Ok(handle) => Some(handle),
-        Err(why) => {
+    let scheduling_problems = game_config.scheduling.validate(scheduling::cpu_count());
+    if !scheduling_problems.is_empty() {
+        session_log.log(&format!(
+            "Invalid scheduling settings, not applying them: {}",
+            scheduling_problems.join(", ")
+        ));
+        Notification::new()
+            .summary("Invalid scheduling settings")
+            .body(&format!(
+                "{}. The priority, affinity and limits are not applied for this launch",
+                scheduling_problems.join(". ")
+            ))
+            .icon("notification_error")
+            .show()
+            .unwrap();
+        game_config.scheduling = Default::default();
+    }
+
+    // Environment that makes the game render on the selected GPU
+    let mut gpu_env = BTreeMap::new();
+    if !game_config.render_on.is_empty() {
+        match gpus
+            .iter()
+            .find(|gpu| gpu.pci_slot == game_config.render_on)
+        {
+            Some(gpu) => {
+                session_log.log(&format!("Rendering on {}", gpu.describe()));
+                gpu_env = gpu.env();
+            }
+            None => {
+                session_log.log(&format!(
+                    "GPU {} not found, rendering on the default GPU",
+                    game_config.render_on
+                ));
+                Notification::new()
+                    .summary("GPU not found")
+                    .body(&format!(
+                        "The GPU at {} was not found, the game renders on the default GPU",
+                        game_config.render_on
+                    ))
+                    .icon("notification_error")
+                    .show()
+                    .unwrap();
+            }
+        }
+    }
+
+    // A missing wrapper would otherwise only show up as the game failing to start
+    let missing_tools = game_config.check_tools(&session_log);
+    if !missing_tools.is_empty() && game_config.missing_tools != MissingToolAction::Ignore {
+        Notification::new()
+            .summary("Missing tools")
+            .body(&format!(
+                "Could not find {} in $PATH{}",
+                missing_tools.join(", "),
+                if game_config.missing_tools == MissingToolAction::Disable {
+                    ", the options using them are disabled for this launch"
+                } else {
+                    ""
+                }
+            ))
+            .icon("notification_error")
+            .show()
+            .unwrap();
+    }
+
+    // The checks that don't wait are done before anything is changed for the launch, so a full
+    // disk or a missing drive doesn't leave pre-launch commands and setups behind
+    let preflight_failures = preflight::run(
+        &game_config.preflight,
+        install_dir.as_deref(),
+        false,
+        &session_log,
+    );
+    if !preflight_failures.is_empty() {
+        notify_preflight_failures(&preflight_failures, &session_log);
+        exit(1);
+    }
+
+    // Snapshot the directories before anything of this session can change them
+    if !game_config.snapshots.directories.is_empty() {
+        if let Err(why) =
+            snapshot::create(&config_dir, &appid, &game_config.snapshots, &session_log)
+        {
+            session_log.log(&format!("Failed to create snapshot: {}", why));
             Notification::new()
-                .summary("Failed to run game")
-                .body(&format!("Game startup failed: {}", why))
+                .summary("Failed to create snapshot")
+                .body(&format!("The snapshot before launching failed: {}", why))
                 .icon("notification_error")
                 .show()
                 .unwrap();
-            None
+        }
+    }
+
+    let mut overlays = Manifest::apply(&state_dir, &appid, &game_config.overlays, &session_log);
+
+    // From here on stopping steamrunner stops the game instead, so the post-exit commands still run
+    signals::install_handlers();
+
+    let mut journal = Journal::create(&state_dir, &appid, &game_config.post_exit_commands);
+
+    // Run all pre launch commands and wait for them to exit before starting game.
+    hooks::run_commands(
+        &game_config.pre_launch_commands,
+        "pre-launch",
+        &state_dir,
+        &session_log,
+    );
+
+    // The paired actions that were set up are undone whatever happens to the launch from here on
+    let undo_actions = hooks::run_setups(&game_config.paired_actions, &session_log);
+    journal.set_undo_actions(&undo_actions);
+
+    // The waiting checks come after the pre-launch commands, which may start what they wait for
+    let preflight_failures = preflight::run(
+        &game_config.preflight,
+        install_dir.as_deref(),
+        true,
+        &session_log,
+    );
+
+    // The pre-launch commands and checks may have run into the end of the allowed hours
+    let playtime_left = playtime_remaining(&state_dir, &appid);
+
+    // Start the game and wait until it exits. It is not started at all if steamrunner was stopped
+    // while the pre-launch commands were running, a preflight check failed or the playtime is up
+    let handle: Option<(GameProcess, Instant)> = if let Some(signal) = signals::take() {
+        session_log.log(&format!(
+            "Received signal {} before the game was started, not starting it",
+            signal
+        ));
+        None
+    } else if !preflight_failures.is_empty() {
+        notify_preflight_failures(&preflight_failures, &session_log);
+        None
+    } else if let Err(why) = &playtime_left {
+        session_log.log(&format!("Not starting the game: {}", why));
+        notify_error(
+            "Playtime limit",
+            &format!("The game was not started. {}", why),
+            &session_log,
+        );
+        None
+    } else {
+        let launch_command = game_config.get_launch_command(&command.to_string());
+        let mut launch_env = game_config.launch_env(is_proton);
+        launch_env.extend(gpu_env);
+        session_log.log(&format!("Starting game: {}", launch_command));
+        for (key, value) in &launch_env {
+            session_log.log(&format!("Game environment: {}={}", key, value));
+        }
+        match GameProcess::spawn(
+            game_config.scheduling.apply_on_exec(
+                Command::new("sh")
+                    .arg("-c")
+                    .arg(&launch_command)
+                    .envs(&launch_env)
+                    .stdout(if matches.is_present("log") {
+                        Stdio::from(
+                            File::create(format!("{}/logs/{}_stdout.log", &config_dir, &appid))
+                                .unwrap(),
+                        )
+                    } else {
+                        Stdio::null()
+                    })
+                    .stderr(if matches.is_present("log") {
+                        Stdio::from(
+                            File::create(format!("{}/logs/{}_stderr.log", &config_dir, &appid))
+                                .unwrap(),
+                        )
+                    } else {
+                        Stdio::null()
+                    }),
+            ),
+        ) {
+            Ok(handle) => {
+                game_config
+                    .scheduling
+                    .log_applied(handle.pid(), &session_log);
+                Some((handle, Instant::now()))
+            }
+            Err(why) => {
+                session_log.log(&format!("Game startup failed: {}", why));
+                notify_error(
+                    "Failed to run game",
+                    &format!("Game startup failed: {}", why),
+                    &session_log,
+                );
+                None
+            }
         }
     };
 
-    // If the game launched successfully, wait until it closes
-    if let Some(mut handle) = handle {
-        handle.wait().unwrap();
+    // If the game launched successfully, wait until it and everything it started have closed. An
+    // error right after starting is most likely caused by one of the tweaks
+    let mut failed_fast = false;
+    if let Some((mut handle, started_at)) = handle {
+        let timer = playtime::Timer::start(
+            playtime::Session::start(&state_dir, &appid),
+            &game_config.playtime,
+            &GLOBAL_CONFIG.lock().unwrap().playtime,
+        );
+        let status = handle
+            .wait(
+                &game_config.process_cleanup,
+                &|| timer.stop_at(),
+                &session_log,
+            )
+            .unwrap();
+        timer.finish();
+        failed_fast = !safe_mode
+            && status.code().map_or(false, |code| code != 0)
+            && started_at.elapsed() < Duration::from_secs(game_config.safe_mode.fast_failure);
+        if failed_fast {
+            session_log.log(&format!(
+                "The game failed within {} seconds ({})",
+                game_config.safe_mode.fast_failure, status
+            ));
+        }
+
+        // The session counts as one of the launches options and commands were enabled for
+        let mut config = GAME_CONFIG.lock().unwrap();
+        if config.count_launch(&game_config, &session_log) {
+            config.save(&format!("{}/game_configs/{}.yaml", config_dir, appid));
+        }
     }
 
-    for command in &game_config.post_exit_commands {
-        if command.enabled {
-            let mut handle = match Command::new("sh").arg("-c").arg(&command.command).spawn() {
-                Ok(handle) => handle,
-                Err(why) => {
-                    Notification::new()
-                        .summary("Failed to run post-exit command")
-                        .body(&format!(
-                            "Failed to run command {}: {}",
-                            &command.command, why
-                        ))
-                        .icon("notification_error")
-                        .show()
-                        .unwrap();
-                    continue;
-                }
-            };
-            handle.wait().unwrap();
+    hooks::run_undos(&undo_actions, &session_log);
+    overlays.revert(&session_log);
+    hooks::run_commands(
+        &game_config.post_exit_commands,
+        "post-exit",
+        &state_dir,
+        &session_log,
+    );
+    journal.remove();
+
+    // Only relaunch once this session is fully torn down, the relaunch is a new session
+    if failed_fast && ask_safe_mode_relaunch(&appid) {
+        session_log.log("Relaunching in safe mode");
+        relaunch_in_safe_mode();
+    }
+}
+
+// Offer to start the game again in safe mode with a notification, as the Start Menu is long closed
+fn ask_safe_mode_relaunch(appid: &str) -> bool {
+    let handle = match Notification::new()
+        .summary("Game failed to start")
+        .body(&format!(
+            "{} exited with an error right after starting, which is usually caused by a wrapper or other tweak",
+            appid
+        ))
+        .action("safe", "Relaunch in safe mode")
+        .action("close", "Close")
+        .icon("notification_error")
+        .show()
+    {
+        Ok(handle) => handle,
+        Err(why) => {
+            println!("Failed to show notification: {}", why);
+            return false;
         }
+    };
+
+    let mut relaunch = false;
+    handle.wait_for_action(|action| relaunch = action == "safe");
+    relaunch
+}
+
+// Replace steamrunner with a new instance started with the same arguments in safe mode, so Steam
+// keeps tracking the same process. The Start Menu was already shown for this launch
+fn relaunch_in_safe_mode() {
+    let args: Vec<String> = env::args()
+        .skip(1)
+        .filter(|arg| arg != "--safe-mode" && arg != "--no-gui")
+        .collect();
+    let why = Command::new(env::current_exe().unwrap())
+        .arg("--safe-mode")
+        .arg("--no-gui")
+        .args(&args)
+        .exec();
+    println!("Failed to relaunch in safe mode: {}", why);
+    exit(1);
+}
+
+fn notify_preflight_failures(failures: &[String], log: &SessionLog) {
+    log.log("Preflight checks failed, not starting the game");
+    notify_error(
+        "Preflight checks failed",
+        &format!("The game was not started. Failed: {}", failures.join(". ")),
+        log,
+    );
+}
+
+// Show an error notification once the paired actions may be set up. Failing to show it is only
+// logged, so steamrunner goes on to undo them
+fn notify_error(summary: &str, body: &str, log: &SessionLog) {
+    if let Err(why) = Notification::new()
+        .summary(summary)
+        .body(body)
+        .icon("notification_error")
+        .show()
+    {
+        log.log(&format!("Failed to show notification: {}", why));
+    }
+}
+
+// The playtime left with the limits of the game and the global config
+fn playtime_remaining(state_dir: &str, appid: &str) -> Result<Option<u64>, String> {
+    playtime::remaining(
+        &GAME_CONFIG.lock().unwrap().playtime,
+        &GLOBAL_CONFIG.lock().unwrap().playtime,
+        state_dir,
+        appid,
+    )
+}
+
+// The restore subcommand, without a snapshot it lists the snapshots of the game instead
+fn restore_snapshot(config_dir: &str, appid: &str, snapshot: Option<&str>) {
+    let snapshot = match snapshot {
+        Some(snapshot) => snapshot,
+        None => {
+            let snapshots = snapshot::list(config_dir, appid);
+            if snapshots.is_empty() {
+                println!("There are no snapshots of {}", appid);
+            }
+            for snapshot in snapshots {
+                println!("{}", snapshot);
+            }
+            return;
+        }
+    };
+
+    match snapshot::restore(config_dir, appid, snapshot) {
+        Ok(directories) => {
+            for directory in directories {
+                println!("Restored {}", directory);
+            }
+        }
+        Err(why) => {
+            println!("{}", why);
+            exit(1);
+        }
+    }
+}
+
+// The enable subcommand, which sets the launch counter of an option or command in the game config
+fn enable_for_next_launches(config_dir: &str, appid: &str, item: &str, launches: Option<&str>) {
+    let launches = match launches.map_or(Ok(1), str::parse::<u32>) {
+        Ok(launches) => launches,
+        Err(why) => {
+            println!("Invalid number of launches: {}", why);
+            exit(1);
+        }
+    };
+    let config_path = format!("{}/game_configs/{}.yaml", config_dir, appid);
+    if !Path::new(&config_path).exists() {
+        println!("There is no config for {}", appid);
+        exit(1);
+    }
+
+    let mut game_config = GameConfig::load(&config_path);
+    if let Err(why) = game_config.enable_for_next_launches(item, launches) {
+        println!("{}", why);
+        exit(1);
+    }
+    game_config.save(&config_path);
+    match launches {
+        0 => println!("{} is no longer enabled for the next launches", item),
+        1 => println!("Enabled {} for the next launch", item),
+        launches => println!("Enabled {} for the next {} launches", item, launches),
     }
 }
 
@@ -240,6 +756,8 @@ fn create_config_dirs(config_dir: &String) {
     fs::create_dir(config_dir).unwrap_or(());
     fs::create_dir(&format!("{}/game_configs", config_dir)).unwrap_or(());
     fs::create_dir(&format!("{}/logs", config_dir)).unwrap_or(());
+    fs::create_dir(&format!("{}/catalogue", config_dir)).unwrap_or(());
+    fs::create_dir(&format!("{}/dxvk", config_dir)).unwrap_or(());
 }
 
 fn create_new_game_config(
@@ -251,7 +769,12 @@ fn create_new_game_config(
         create_config_dirs(config_dir);
     }
 
-    global_config.save(&format!("{}/game_configs/{}.yaml", config_dir, appid));
+    // The MangoHud settings are left empty, so the game follows the global ones until changed
+    let mut game_config = (*global_config).clone();
+    game_config.mangohud = Default::default();
+    // The global playtime limits already count the playtime of every game
+    game_config.playtime = Default::default();
+    game_config.save(&format!("{}/game_configs/{}.yaml", config_dir, appid));
 
-    (*global_config).clone()
+    game_config
 }
@@ -0,0 +1,79 @@
+use serde::{Deserialize, Serialize};
+
+pub const POSITIONS: [&str; 6] = [
+    "top-left",
+    "top-right",
+    "top-center",
+    "bottom-left",
+    "bottom-right",
+    "bottom-center",
+];
+
+// MangoHud settings turned into MANGOHUD_CONFIG at launch. Every setting that is not set in a game
+// config falls back to the one in the global config
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct MangoHudConfig {
+    // Whether steamrunner sets MANGOHUD_CONFIG at all, the HUD itself is still enabled with the
+    // mangohud wrapper
+    pub enabled: Option<bool>,
+    pub fps_limit: Option<u32>,
+    pub position: Option<String>,
+    // Metrics toggled on, like cpu_temp, gpu_temp, ram, vram or frametime
+    pub metrics: Option<Vec<String>>,
+    // Folder MangoHud writes its logs into
+    pub log_folder: Option<String>,
+}
+
+impl MangoHudConfig {
+    // Fill the settings that are not set with the ones from the defaults
+    pub fn with_defaults(&self, defaults: &MangoHudConfig) -> Self {
+        Self {
+            enabled: self.enabled.or(defaults.enabled),
+            fps_limit: self.fps_limit.or(defaults.fps_limit),
+            position: self.position.clone().or_else(|| defaults.position.clone()),
+            metrics: self.metrics.clone().or_else(|| defaults.metrics.clone()),
+            log_folder: self
+                .log_folder
+                .clone()
+                .or_else(|| defaults.log_folder.clone()),
+        }
+    }
+
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        if let Some(position) = &self.position {
+            if !POSITIONS.contains(&position.as_str()) {
+                problems.push(format!("Unknown HUD position {}", position));
+            }
+        }
+        for metric in self.metrics.iter().flatten() {
+            if metric.contains(|chr: char| chr == ',' || chr == '=' || chr.is_whitespace()) {
+                problems.push(format!("Invalid metric {}", metric));
+            }
+        }
+
+        problems
+    }
+
+    // The value for MANGOHUD_CONFIG, None when steamrunner shouldn't set it
+    pub fn config_string(&self) -> Option<String> {
+        if self.enabled != Some(true) {
+            return None;
+        }
+
+        let mut options: Vec<String> = Vec::new();
+        if let Some(fps_limit) = self.fps_limit {
+            options.push(format!("fps_limit={}", fps_limit));
+        }
+        if let Some(position) = &self.position {
+            options.push(format!("position={}", position));
+        }
+        options.extend(self.metrics.iter().flatten().cloned());
+        if let Some(log_folder) = &self.log_folder {
+            options.push(format!("output_folder={}", log_folder));
+        }
+
+        Some(options.join(","))
+    }
+}
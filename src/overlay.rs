@@ -0,0 +1,203 @@
+use std::{
+    fs::{self, File},
+    io,
+    path::Path,
+    process,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{journal, session_log::SessionLog, snapshot::expand_home};
+
+// Suffix of the original file while an overlay replaces it. The original stays next to the target
+// so it can be moved back without copying, even on another filesystem than the state dir
+const ORIGINAL_SUFFIX: &str = ".steamrunner-original";
+
+// A file copied over target for the duration of a session
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct FileOverlay {
+    pub source: String,
+    pub target: String,
+    pub enabled: bool,
+}
+
+// Record of the overlays applied by a session, in overlays/<appid>_<pid>.yaml in the state dir.
+// Entries are written before the target is touched, so a crash can always be reverted
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Manifest {
+    appid: String,
+    pid: u32,
+    entries: Vec<ManifestEntry>,
+    #[serde(skip)]
+    path: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ManifestEntry {
+    target: String,
+    // Where the original file was moved to, None when the overlay added a new file
+    original: Option<String>,
+    #[serde(default)]
+    source: String,
+}
+
+impl ManifestEntry {
+    fn revert(&self) -> io::Result<()> {
+        match &self.original {
+            // Only replace the target once the original is known to still be there
+            Some(original) if Path::new(original).exists() => fs::rename(original, &self.target),
+            // Interrupted before the original was moved aside, so the target still is the original
+            Some(_)
+                if Path::new(&self.target).exists()
+                    && !same_contents(&self.source, &self.target) =>
+            {
+                Ok(())
+            }
+            Some(original) => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("The original {} is gone", original),
+            )),
+            None => match fs::remove_file(&self.target) {
+                Err(why) if why.kind() == io::ErrorKind::NotFound => Ok(()),
+                result => result,
+            },
+        }
+    }
+}
+
+fn same_contents(first: &str, second: &str) -> bool {
+    match (fs::read(first), fs::read(second)) {
+        (Ok(first), Ok(second)) => first == second,
+        _ => false,
+    }
+}
+
+impl Manifest {
+    // Apply the enabled overlays. Overlays that fail are logged and skipped
+    pub fn apply(state_dir: &str, appid: &str, overlays: &[FileOverlay], log: &SessionLog) -> Self {
+        let overlay_dir = format!("{}/overlays", state_dir);
+        fs::create_dir_all(&overlay_dir).unwrap_or(());
+
+        let mut manifest = Self {
+            appid: appid.to_string(),
+            pid: process::id(),
+            entries: Vec::new(),
+            path: format!("{}/{}_{}.yaml", overlay_dir, appid, process::id()),
+        };
+
+        for overlay in overlays.iter().filter(|overlay| overlay.enabled) {
+            let source = expand_home(&overlay.source);
+            let target = expand_home(&overlay.target);
+            match manifest.apply_one(&source, &target) {
+                Ok(()) => log.log(&format!("Applied overlay {} over {}", source, target)),
+                Err(why) => log.log(&format!(
+                    "Failed to apply overlay {} over {}: {}",
+                    source, target, why
+                )),
+            }
+        }
+
+        manifest
+    }
+
+    fn apply_one(&mut self, source: &str, target: &str) -> io::Result<()> {
+        if !Path::new(source).is_file() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "The source is not a file",
+            ));
+        }
+
+        let original = if Path::new(target).exists() {
+            let original = format!("{}{}", target, ORIGINAL_SUFFIX);
+            if Path::new(&original).exists() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{} already exists", original),
+                ));
+            }
+            Some(original)
+        } else {
+            None
+        };
+
+        self.entries.push(ManifestEntry {
+            target: target.to_string(),
+            original: original.clone(),
+            source: source.to_string(),
+        });
+        self.write()?;
+
+        if let Some(original) = &original {
+            fs::rename(target, original)?;
+        }
+        if let Some(parent) = Path::new(target).parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(source, target)?;
+
+        Ok(())
+    }
+
+    fn write(&self) -> io::Result<()> {
+        fs::write(&self.path, serde_yaml::to_string(self).unwrap())
+    }
+
+    // Remove the overlays in reverse order and put the original files back. Overlays that can't be
+    // reverted stay in the manifest, so they are tried again on the next start
+    pub fn revert(&mut self, log: &SessionLog) {
+        let mut failed = Vec::new();
+        for entry in self.entries.drain(..).rev() {
+            match entry.revert() {
+                Ok(()) => log.log(&format!("Reverted overlay of {}", entry.target)),
+                Err(why) => {
+                    log.log(&format!(
+                        "Failed to revert overlay of {}: {}",
+                        entry.target, why
+                    ));
+                    failed.push(entry);
+                }
+            }
+        }
+
+        if failed.is_empty() {
+            fs::remove_file(&self.path).unwrap_or(());
+        } else {
+            failed.reverse();
+            self.entries = failed;
+            if let Err(why) = self.write() {
+                log.log(&format!("Failed to write {}: {}", self.path, why));
+            }
+        }
+    }
+
+    // Revert the overlays left behind by sessions of steamrunner processes that no longer exist
+    pub fn revert_leftover(state_dir: &str, log: &SessionLog) {
+        for entry in fs::read_dir(format!("{}/overlays", state_dir))
+            .into_iter()
+            .flatten()
+            .flatten()
+        {
+            let path = entry.path().to_string_lossy().to_string();
+            let mut manifest: Manifest = match File::open(&path)
+                .ok()
+                .and_then(|file| serde_yaml::from_reader(file).ok())
+            {
+                Some(manifest) => manifest,
+                None => {
+                    println!("Ignoring unreadable overlay manifest {}", path);
+                    continue;
+                }
+            };
+            manifest.path = path;
+
+            if !journal::is_running(manifest.pid) {
+                log.log(&format!(
+                    "Reverting the overlays of the interrupted session of {} (pid {})",
+                    manifest.appid, manifest.pid
+                ));
+                manifest.revert(log);
+            }
+        }
+    }
+}
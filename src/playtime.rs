@@ -0,0 +1,659 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, OpenOptions},
+    io::Write,
+    process,
+    sync::{
+        mpsc::{self, RecvTimeoutError, Sender},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use notify_rust::Notification;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    journal,
+    time::{self, now},
+};
+
+// Weekdays in the order of tm_wday, as used for the keys of the allowed hours
+const WEEKDAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+// How often the record of a running session is brought up to date
+const TOUCH_INTERVAL: Duration = Duration::from_secs(60);
+
+// How often the remaining playtime is checked again while the game runs, as other games played at
+// the same time count towards the global limits too
+const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+// Used when neither the game nor the global config sets them
+const DEFAULT_WARN_BEFORE: [u64; 3] = [10, 5, 1];
+const DEFAULT_GRACE_SECONDS: u64 = 60;
+
+// Limits on when and how long games are played. The limits in the game config apply to the
+// playtime of that game, the ones in the global config to the playtime of all games together
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct PlaytimeLimits {
+    // Minutes of play per day, 0 for no limit
+    pub daily_minutes: u64,
+    // Time windows play is allowed in per weekday, like mon: 16:00-19:00, 20:00-21:00. Days that
+    // aren't listed have no restriction, an empty value allows no play at all that day
+    pub allowed_hours: BTreeMap<String, String>,
+    // Minutes before the time is up to warn at. This and the settings below follow the global
+    // config in games that leave them unset
+    pub warn_before: Option<Vec<u64>>,
+    // Seconds the game keeps running after the time is up before it is stopped
+    pub grace_seconds: Option<u64>,
+    // Minutes between reminders of how long the game has been played, 0 for none
+    pub remind_every: Option<u64>,
+}
+
+impl PlaytimeLimits {
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        for (day, windows) in &self.allowed_hours {
+            if !WEEKDAYS.contains(&day.as_str()) {
+                problems.push(format!(
+                    "Unknown weekday {}, use {}",
+                    day,
+                    WEEKDAYS.join(", ")
+                ));
+            }
+            if let Err(why) = parse_windows(windows) {
+                problems.push(why);
+            }
+        }
+
+        problems
+    }
+
+    // The allowed windows of a weekday in seconds since midnight, None when the day is unrestricted
+    fn windows(&self, weekday: usize) -> Result<Option<Vec<(u64, u64)>>, String> {
+        match self.allowed_hours.get(WEEKDAYS[weekday]) {
+            Some(windows) => parse_windows(windows).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+// A time like 16:30 in seconds since midnight, 24:00 being the end of the day
+fn parse_time(time: &str) -> Option<u64> {
+    let (hours, minutes) = time.trim().split_once(':')?;
+    let (hours, minutes): (u64, u64) = (hours.parse().ok()?, minutes.parse().ok()?);
+    if minutes >= 60 || hours * 60 + minutes > 24 * 60 {
+        return None;
+    }
+    Some((hours * 60 + minutes) * 60)
+}
+
+fn parse_windows(windows: &str) -> Result<Vec<(u64, u64)>, String> {
+    windows
+        .split(',')
+        .filter(|window| !window.trim().is_empty())
+        .map(|window| {
+            window
+                .split_once('-')
+                .and_then(|(start, end)| Some((parse_time(start)?, parse_time(end)?)))
+                .filter(|(start, end)| start < end)
+                .ok_or(format!("Invalid time window {}", window.trim()))
+        })
+        .collect()
+}
+
+fn format_seconds_of_day(seconds: u64) -> String {
+    format!("{:02}:{:02}", seconds / 3600, seconds / 60 % 60)
+}
+
+// The start of the current local day and its weekday
+fn today(now: u64) -> (u64, usize) {
+    (
+        time::start_of_day(now),
+        time::local_time(now).tm_wday as usize,
+    )
+}
+
+fn playtime_dir(state_dir: &str) -> String {
+    format!("{}/playtime", state_dir)
+}
+
+fn append_record(state_dir: &str, appid: &str, start: u64, end: u64) {
+    let path = format!("{}/{}.log", playtime_dir(state_dir), appid);
+    let written = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| writeln!(file, "{} {}", start, end));
+    if let Err(why) = written {
+        println!("Failed to write playtime record {}: {}", path, why);
+    }
+}
+
+fn parse_record(line: &str) -> Option<(u64, u64)> {
+    let (start, end) = line.trim().split_once(' ')?;
+    Some((start.parse().ok()?, end.parse().ok()?))
+}
+
+// A game being played. While it runs it is kept in playtime/<appid>_<pid>.running in the state
+// dir as its start and the last time it was seen running, once it ends it is added to
+// playtime/<appid>.log as a "start end" line
+pub struct Session {
+    state_dir: String,
+    appid: String,
+    start: u64,
+    path: String,
+}
+
+impl Session {
+    pub fn start(state_dir: &str, appid: &str) -> Self {
+        fs::create_dir_all(playtime_dir(state_dir)).unwrap_or(());
+        let session = Self {
+            state_dir: state_dir.to_string(),
+            appid: appid.to_string(),
+            start: now(),
+            path: format!(
+                "{}/{}_{}.running",
+                playtime_dir(state_dir),
+                appid,
+                process::id()
+            ),
+        };
+        touch(&session.path, session.start);
+
+        session
+    }
+
+    fn finish(&self) {
+        append_record(&self.state_dir, &self.appid, self.start, now());
+        fs::remove_file(&self.path).unwrap_or(());
+    }
+}
+
+// Record that the session is still running
+fn touch(path: &str, start: u64) {
+    fs::write(path, format!("{} {}", start, now())).unwrap_or(());
+}
+
+// The running sessions of other steamrunner processes as (appid, pid, start, last seen)
+fn running_sessions(state_dir: &str) -> Vec<(String, u32, u64, u64)> {
+    fs::read_dir(playtime_dir(state_dir))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let (appid, pid) = name.strip_suffix(".running")?.rsplit_once('_')?;
+            let (start, last_seen) = parse_record(&fs::read_to_string(entry.path()).ok()?)?;
+            Some((appid.to_string(), pid.parse().ok()?, start, last_seen))
+        })
+        .collect()
+}
+
+// Add the sessions of steamrunner processes that no longer exist to the records, up to the last
+// time they were seen running
+pub fn collect_leftover(state_dir: &str) {
+    for (appid, pid, start, last_seen) in running_sessions(state_dir) {
+        if !journal::is_running(pid) {
+            append_record(state_dir, &appid, start, last_seen);
+            fs::remove_file(format!(
+                "{}/{}_{}.running",
+                playtime_dir(state_dir),
+                appid,
+                pid
+            ))
+            .unwrap_or(());
+        }
+    }
+}
+
+// Seconds played since the start of the day, of one game or of all of them
+fn played_since(state_dir: &str, appid: Option<&str>, since: u64, now: u64) -> u64 {
+    let overlap = |(start, end): (u64, u64)| end.min(now).saturating_sub(start.max(since));
+
+    let recorded: u64 = fs::read_dir(playtime_dir(state_dir))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            match (name.strip_suffix(".log"), appid) {
+                (Some(name), Some(appid)) => name == appid,
+                (Some(_), None) => true,
+                (None, _) => false,
+            }
+        })
+        .map(|entry| {
+            fs::read_to_string(entry.path())
+                .unwrap_or_default()
+                .lines()
+                .filter_map(parse_record)
+                .map(overlap)
+                .sum::<u64>()
+        })
+        .sum();
+    let running: u64 = running_sessions(state_dir)
+        .into_iter()
+        .filter(|(running_appid, ..)| appid.map_or(true, |appid| appid == running_appid))
+        .map(|(_, _, start, _)| overlap((start, now)))
+        .sum();
+
+    recorded + running
+}
+
+// Seconds of play left right now, None when there is no limit. Fails with the reason when the
+// game can't be played at all
+pub fn remaining(
+    game_limits: &PlaytimeLimits,
+    global_limits: &PlaytimeLimits,
+    state_dir: &str,
+    appid: &str,
+) -> Result<Option<u64>, String> {
+    let now = now();
+    let (midnight, weekday) = today(now);
+    let mut remaining: Option<u64> = None;
+
+    for (limits, appid, scope) in [
+        (game_limits, Some(appid), "this game"),
+        (global_limits, None, "all games"),
+    ] {
+        if limits.daily_minutes > 0 {
+            let played = played_since(state_dir, appid, midnight, now);
+            let left = (limits.daily_minutes * 60).saturating_sub(played);
+            if left == 0 {
+                return Err(format!(
+                    "The daily playtime of {} minutes for {} is used up",
+                    limits.daily_minutes, scope
+                ));
+            }
+            remaining = Some(remaining.map_or(left, |remaining| remaining.min(left)));
+        }
+
+        if let Some(windows) = limits.windows(weekday)? {
+            let time_of_day = now - midnight;
+            match windows
+                .iter()
+                .find(|(start, end)| (*start..*end).contains(&time_of_day))
+            {
+                Some((_, end)) => {
+                    let left = end - time_of_day;
+                    remaining = Some(remaining.map_or(left, |remaining| remaining.min(left)));
+                }
+                None => {
+                    let allowed: Vec<String> = windows
+                        .iter()
+                        .map(|(start, end)| {
+                            format!(
+                                "{}-{}",
+                                format_seconds_of_day(*start),
+                                format_seconds_of_day(*end)
+                            )
+                        })
+                        .collect();
+                    return Err(format!(
+                        "Playing {} is only allowed {} today",
+                        scope,
+                        if allowed.is_empty() {
+                            "on other days".to_string()
+                        } else {
+                            format!("at {}", allowed.join(", "))
+                        }
+                    ));
+                }
+            }
+        }
+    }
+
+    Ok(remaining)
+}
+
+// The remaining playtime for the Start Menu
+pub fn describe_remaining(remaining: &Result<Option<u64>, String>) -> String {
+    match remaining {
+        Ok(Some(remaining)) => format!("{} minutes of playtime left", remaining / 60),
+        Ok(None) => String::new(),
+        Err(why) => format!("{}, the game won't start", why),
+    }
+}
+
+// A duration of play for the reminders, like 2h or 1h 30m
+fn format_played(seconds: u64) -> String {
+    let (hours, minutes) = (seconds / 3600, seconds % 3600 / 60);
+    match (hours, minutes) {
+        (0, minutes) => format!("{}m", minutes),
+        (hours, 0) => format!("{}h", hours),
+        (hours, minutes) => format!("{}h {}m", hours, minutes),
+    }
+}
+
+fn notify(summary: &str, body: &str) {
+    if let Err(why) = Notification::new().summary(summary).body(body).show() {
+        println!("Failed to show notification: {}", why);
+    }
+}
+
+// Keeps the record of the session up to date while the game runs, warns before the playtime is up
+// and reminds of how long the game has been played. Runs on a thread of its own so the
+// notifications never hold up the game. The remaining playtime is checked again every few seconds,
+// so the time other games are played at the same time counts as well
+pub struct Timer {
+    stop: Sender<()>,
+    thread: JoinHandle<()>,
+    session: Session,
+    // When the game has to be stopped, set once the time is up
+    stop_at: Arc<Mutex<Option<Instant>>>,
+}
+
+impl Timer {
+    pub fn start(
+        session: Session,
+        game_limits: &PlaytimeLimits,
+        global_limits: &PlaytimeLimits,
+    ) -> Self {
+        let warn_before = game_limits
+            .warn_before
+            .clone()
+            .or_else(|| global_limits.warn_before.clone())
+            .unwrap_or_else(|| DEFAULT_WARN_BEFORE.to_vec());
+        let grace_seconds = game_limits
+            .grace_seconds
+            .or(global_limits.grace_seconds)
+            .unwrap_or(DEFAULT_GRACE_SECONDS);
+        let remind_every = game_limits
+            .remind_every
+            .or(global_limits.remind_every)
+            .unwrap_or(0);
+
+        let (stop, stopped) = mpsc::channel();
+        let stop_at = Arc::new(Mutex::new(None));
+        let thread = thread::spawn({
+            let (game_limits, global_limits) = (game_limits.clone(), global_limits.clone());
+            let (state_dir, appid) = (session.state_dir.clone(), session.appid.clone());
+            let (path, start) = (session.path.clone(), session.start);
+            let stop_at = stop_at.clone();
+            move || {
+                let started = Instant::now();
+                let mut next_touch = started + TOUCH_INTERVAL;
+                let remind_interval = Duration::from_secs(remind_every * 60);
+                let mut next_reminder = (remind_every > 0).then(|| started + remind_interval);
+                // None once the time is up
+                let mut next_check = Some(started);
+                // The seconds that were left at the last check, so every warning is only given
+                // once. Warnings that were already due at the start are not given at all
+                let mut last_left: Option<u64> = None;
+                loop {
+                    let next = next_check.map_or(next_touch, |check| next_touch.min(check));
+                    let next = next_reminder.map_or(next, |reminder| next.min(reminder));
+                    match stopped.recv_timeout(next.saturating_duration_since(Instant::now())) {
+                        Err(RecvTimeoutError::Timeout) => (),
+                        _ => break,
+                    }
+
+                    if next_check.map_or(false, |check| check <= Instant::now()) {
+                        next_check =
+                            match remaining(&game_limits, &global_limits, &state_dir, &appid) {
+                                Ok(left) => {
+                                    let left = left.unwrap_or(u64::MAX);
+                                    let passed = warn_before.iter().any(|minutes| {
+                                        left <= minutes * 60
+                                            && last_left.map_or(false, |last| minutes * 60 < last)
+                                    });
+                                    if passed {
+                                        notify(
+                                            "Playtime",
+                                            &format!(
+                                                "{} minutes of playtime left",
+                                                (left + 59) / 60
+                                            ),
+                                        );
+                                    }
+                                    last_left = Some(left);
+
+                                    // Wake up in time for the next warning and the end of the time
+                                    let next_warning = warn_before
+                                        .iter()
+                                        .map(|minutes| minutes * 60)
+                                        .filter(|seconds| *seconds < left)
+                                        .max()
+                                        .unwrap_or(0);
+                                    Some(
+                                        Instant::now()
+                                            + CHECK_INTERVAL
+                                                .min(Duration::from_secs(left - next_warning)),
+                                    )
+                                }
+                                Err(why) => {
+                                    notify(
+                                        "Playtime",
+                                        &format!(
+                                            "{}, the game is stopped in {} seconds",
+                                            why, grace_seconds
+                                        ),
+                                    );
+                                    *stop_at.lock().unwrap() =
+                                        Some(Instant::now() + Duration::from_secs(grace_seconds));
+                                    None
+                                }
+                            };
+                    }
+                    if let Some(reminder) = next_reminder.filter(|due| *due <= Instant::now()) {
+                        notify(
+                            "Playtime",
+                            &format!(
+                                "You've been playing for {}",
+                                format_played((reminder - started).as_secs())
+                            ),
+                        );
+                        next_reminder = Some(reminder + remind_interval);
+                    }
+                    if Instant::now() >= next_touch {
+                        touch(&path, start);
+                        next_touch += TOUCH_INTERVAL;
+                    }
+                }
+            }
+        });
+
+        Self {
+            stop,
+            thread,
+            session,
+            stop_at,
+        }
+    }
+
+    // When the game has to be stopped, None while there is time left
+    pub fn stop_at(&self) -> Option<Instant> {
+        *self.stop_at.lock().unwrap()
+    }
+
+    // Stop the timer and add the session to the records
+    pub fn finish(self) {
+        self.stop.send(()).unwrap_or(());
+        self.thread.join().unwrap_or(());
+        self.session.finish();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, path::PathBuf};
+
+    // A state dir with playtime records, removed again when dropped
+    struct StateDir(PathBuf);
+
+    impl StateDir {
+        fn new(name: &str) -> Self {
+            let dir =
+                env::temp_dir().join(format!("steamrunner-playtime-{}-{}", name, process::id()));
+            fs::remove_dir_all(&dir).unwrap_or(());
+            fs::create_dir_all(dir.join("playtime")).unwrap();
+            Self(dir)
+        }
+
+        fn path(&self) -> &str {
+            self.0.to_str().unwrap()
+        }
+    }
+
+    impl Drop for StateDir {
+        fn drop(&mut self) {
+            fs::remove_dir_all(&self.0).unwrap_or(());
+        }
+    }
+
+    fn daily(minutes: u64) -> PlaytimeLimits {
+        PlaytimeLimits {
+            daily_minutes: minutes,
+            ..Default::default()
+        }
+    }
+
+    fn allowed_today(windows: &str) -> PlaytimeLimits {
+        let mut limits = PlaytimeLimits::default();
+        limits.allowed_hours.insert(
+            WEEKDAYS[time::local_time(now()).tm_wday as usize].to_string(),
+            windows.to_string(),
+        );
+        limits
+    }
+
+    // Record a session of the game that ended just now, as long as possible without starting
+    // before midnight. Returns its length
+    fn record_today(state_dir: &StateDir, appid: &str, seconds: u64) -> u64 {
+        let now = now();
+        let seconds = seconds.min(now - time::start_of_day(now));
+        append_record(state_dir.path(), appid, now - seconds, now);
+        seconds
+    }
+
+    #[test]
+    fn time_windows() {
+        assert_eq!(parse_windows(""), Ok(vec![]));
+        assert_eq!(
+            parse_windows("16:00-19:00, 20:30-21:00"),
+            Ok(vec![(16 * 3600, 19 * 3600), (20 * 3600 + 1800, 21 * 3600)])
+        );
+        assert_eq!(parse_windows(" 0:00 - 24:00 "), Ok(vec![(0, 24 * 3600)]));
+        for invalid in [
+            "19:00-16:00",
+            "10:00-10:00",
+            "24:01-24:30",
+            "10:60-11:00",
+            "10-11",
+            "10:00",
+        ] {
+            assert_eq!(
+                parse_windows(invalid),
+                Err(format!("Invalid time window {}", invalid))
+            );
+        }
+    }
+
+    #[test]
+    fn validate_allowed_hours() {
+        let mut limits = PlaytimeLimits::default();
+        limits
+            .allowed_hours
+            .insert("mon".to_string(), "16:00-19:00".to_string());
+        limits
+            .allowed_hours
+            .insert("sat".to_string(), String::new());
+        assert!(limits.validate().is_empty());
+
+        limits
+            .allowed_hours
+            .insert("monday".to_string(), "16:00-19:00".to_string());
+        limits
+            .allowed_hours
+            .insert("sun".to_string(), "19:00-16:00".to_string());
+        assert_eq!(
+            limits.validate(),
+            [
+                "Unknown weekday monday, use sun, mon, tue, wed, thu, fri, sat",
+                "Invalid time window 19:00-16:00"
+            ]
+        );
+    }
+
+    #[test]
+    fn no_limits() {
+        let state_dir = StateDir::new("none");
+        let none = PlaytimeLimits::default();
+        assert_eq!(remaining(&none, &none, state_dir.path(), "1"), Ok(None));
+    }
+
+    #[test]
+    fn daily_minutes() {
+        let state_dir = StateDir::new("daily");
+        let played = record_today(&state_dir, "1", 600);
+        let other = record_today(&state_dir, "2", 300);
+        let none = PlaytimeLimits::default();
+
+        // Only the game's own playtime counts towards its limit
+        let left = remaining(&daily(60), &none, state_dir.path(), "1")
+            .unwrap()
+            .unwrap();
+        assert_eq!(left, 3600 - played);
+
+        // The global limit counts every game, and the lower limit wins
+        let left = remaining(&daily(600), &daily(60), state_dir.path(), "1")
+            .unwrap()
+            .unwrap();
+        assert_eq!(left, 3600 - played - other);
+    }
+
+    #[test]
+    fn daily_minutes_used_up() {
+        let now = now();
+        if now - time::start_of_day(now) < 60 {
+            // Too close to midnight for a minute of play today
+            return;
+        }
+        let state_dir = StateDir::new("used-up");
+        // A session that is still running counts too
+        fs::write(
+            format!("{}/playtime/1_1.running", state_dir.path()),
+            format!("{} {}", time::start_of_day(now), now),
+        )
+        .unwrap();
+
+        assert_eq!(
+            remaining(&daily(1), &PlaytimeLimits::default(), state_dir.path(), "1"),
+            Err("The daily playtime of 1 minutes for this game is used up".to_string())
+        );
+        assert_eq!(
+            remaining(&PlaytimeLimits::default(), &daily(1), state_dir.path(), "2"),
+            Err("The daily playtime of 1 minutes for all games is used up".to_string())
+        );
+    }
+
+    #[test]
+    fn allowed_hours() {
+        let state_dir = StateDir::new("hours");
+        let none = PlaytimeLimits::default();
+
+        let now = now();
+        let end_of_day = time::start_of_day(now) + 24 * 3600;
+        let left = remaining(&allowed_today("00:00-24:00"), &none, state_dir.path(), "1")
+            .unwrap()
+            .unwrap();
+        assert!((end_of_day - now - 1..=end_of_day - now).contains(&left));
+
+        assert_eq!(
+            remaining(&none, &allowed_today(""), state_dir.path(), "1"),
+            Err("Playing all games is only allowed on other days today".to_string())
+        );
+        let time_of_day = now - time::start_of_day(now);
+        if time_of_day < 23 * 3600 {
+            assert_eq!(
+                remaining(&allowed_today("23:00-24:00"), &none, state_dir.path(), "1"),
+                Err("Playing this game is only allowed at 23:00-24:00 today".to_string())
+            );
+        }
+    }
+}
@@ -0,0 +1,244 @@
+use std::{
+    ffi::CString,
+    fs,
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
+    path::Path,
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{session_log::SessionLog, signals, snapshot::expand_home};
+
+// Time between two attempts of a waiting check
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum PreflightKind {
+    // At least amount MiB free on the filesystem of the path
+    FreeSpace,
+    Exists,
+    MountPoint,
+    // Wait up to amount seconds until the path exists
+    WaitForPath,
+    // Wait up to amount seconds until the port accepts connections, the target is a port on
+    // localhost or a host:port
+    WaitForPort,
+}
+
+impl Default for PreflightKind {
+    fn default() -> Self {
+        PreflightKind::Exists
+    }
+}
+
+impl PreflightKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PreflightKind::FreeSpace => "free space",
+            PreflightKind::Exists => "exists",
+            PreflightKind::MountPoint => "mount point",
+            PreflightKind::WaitForPath => "wait for path",
+            PreflightKind::WaitForPort => "wait for port",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "free space" => Some(PreflightKind::FreeSpace),
+            "exists" => Some(PreflightKind::Exists),
+            "mount point" => Some(PreflightKind::MountPoint),
+            "wait for path" => Some(PreflightKind::WaitForPath),
+            "wait for port" => Some(PreflightKind::WaitForPort),
+            _ => None,
+        }
+    }
+}
+
+// A check that has to pass before the game is started. Paths that are left empty mean the install
+// dir of the game
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct PreflightCheck {
+    pub kind: PreflightKind,
+    pub target: String,
+    // MiB for free space, seconds for the waiting checks
+    #[serde(default)]
+    pub amount: u64,
+    pub enabled: bool,
+}
+
+impl PreflightCheck {
+    fn waits(&self) -> bool {
+        self.kind == PreflightKind::WaitForPath || self.kind == PreflightKind::WaitForPort
+    }
+
+    fn path(&self, install_dir: Option<&str>) -> Result<String, String> {
+        match (self.target.trim(), install_dir) {
+            ("", Some(install_dir)) => Ok(install_dir.to_string()),
+            ("", None) => {
+                Err("no path is set and the install dir of the game is unknown".to_string())
+            }
+            (target, _) => Ok(expand_home(target)),
+        }
+    }
+
+    // What the check makes sure of, to name it in messages
+    pub fn describe(&self, install_dir: Option<&str>) -> String {
+        let target = self
+            .path(install_dir)
+            .unwrap_or_else(|_| "the install dir".to_string());
+        match self.kind {
+            PreflightKind::FreeSpace => format!("{} MiB free under {}", self.amount, target),
+            PreflightKind::Exists => format!("{} exists", target),
+            PreflightKind::MountPoint => format!("{} is mounted", target),
+            PreflightKind::WaitForPath => format!("{} exists within {}s", target, self.amount),
+            PreflightKind::WaitForPort => format!(
+                "port {} accepts connections within {}s",
+                self.target.trim(),
+                self.amount
+            ),
+        }
+    }
+
+    fn check(&self, install_dir: Option<&str>) -> Result<(), String> {
+        match self.kind {
+            PreflightKind::FreeSpace => {
+                let path = self.path(install_dir)?;
+                let free =
+                    free_space(&path).ok_or(format!("can't read the free space of {}", path))?;
+                if free < self.amount.saturating_mul(1024 * 1024) {
+                    return Err(format!("only {} MiB free", free / 1024 / 1024));
+                }
+            }
+            PreflightKind::Exists => {
+                if !Path::new(&self.path(install_dir)?).exists() {
+                    return Err("it does not exist".to_string());
+                }
+            }
+            PreflightKind::MountPoint => {
+                if !is_mount_point(&self.path(install_dir)?) {
+                    return Err("nothing is mounted there".to_string());
+                }
+            }
+            PreflightKind::WaitForPath => {
+                let path = self.path(install_dir)?;
+                wait_until(self.amount, || Path::new(&path).exists())?;
+            }
+            PreflightKind::WaitForPort => {
+                let addresses = socket_addrs(&self.target)?;
+                wait_until(self.amount, || {
+                    addresses
+                        .iter()
+                        .any(|address| TcpStream::connect_timeout(address, POLL_INTERVAL).is_ok())
+                })?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Poll until the condition holds, for at most the given number of seconds. Gives up early when
+// steamrunner is being stopped
+fn wait_until(seconds: u64, mut condition: impl FnMut() -> bool) -> Result<(), String> {
+    let start = Instant::now();
+    loop {
+        if condition() {
+            return Ok(());
+        }
+        if signals::pending() {
+            return Err("steamrunner was stopped while waiting".to_string());
+        }
+        if start.elapsed() >= Duration::from_secs(seconds) {
+            return Err(format!("timed out after {}s", seconds));
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+fn socket_addrs(target: &str) -> Result<Vec<SocketAddr>, String> {
+    let target = target.trim();
+    let target = if target.parse::<u16>().is_ok() {
+        format!("localhost:{}", target)
+    } else {
+        target.to_string()
+    };
+
+    target
+        .to_socket_addrs()
+        .map(|addresses| addresses.collect())
+        .map_err(|why| format!("invalid address {}: {}", target, why))
+}
+
+// Bytes available to unprivileged users on the filesystem of the path
+fn free_space(path: &str) -> Option<u64> {
+    let path = CString::new(path).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+// Whether the path is listed in /proc/self/mounts, where spaces and tabs are escaped in octal
+fn is_mount_point(path: &str) -> bool {
+    let path = path.trim_end_matches('/');
+    fs::read_to_string("/proc/self/mounts")
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| line.split(' ').nth(1))
+        .map(|mount_point| {
+            mount_point
+                .replace("\\040", " ")
+                .replace("\\011", "\t")
+                .replace("\\134", "\\")
+        })
+        .any(|mount_point| mount_point.trim_end_matches('/') == path)
+}
+
+// Run the enabled checks that don't wait, for showing problems on the Start Menu. Returns the
+// failed checks
+pub fn check_now(checks: &[PreflightCheck], install_dir: Option<&str>) -> Vec<String> {
+    checks
+        .iter()
+        .filter(|check| check.enabled && !check.waits())
+        .filter_map(|check| {
+            check
+                .check(install_dir)
+                .err()
+                .map(|why| format!("{}: {}", check.describe(install_dir), why))
+        })
+        .collect()
+}
+
+// Run the enabled checks that wait, or the ones that don't, in order. Returns the failed checks
+pub fn run(
+    checks: &[PreflightCheck],
+    install_dir: Option<&str>,
+    waiting: bool,
+    log: &SessionLog,
+) -> Vec<String> {
+    let mut failures = Vec::new();
+
+    for check in checks
+        .iter()
+        .filter(|check| check.enabled && check.waits() == waiting)
+    {
+        let start = Instant::now();
+        match check.check(install_dir) {
+            Ok(()) => log.log(&format!(
+                "Preflight check passed in {:.3}s: {}",
+                start.elapsed().as_secs_f64(),
+                check.describe(install_dir)
+            )),
+            Err(why) => {
+                let failure = format!("{}: {}", check.describe(install_dir), why);
+                log.log(&format!("Preflight check failed: {}", failure));
+                failures.push(failure);
+            }
+        }
+    }
+
+    failures
+}
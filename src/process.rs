@@ -0,0 +1,280 @@
+use std::{
+    collections::BTreeSet,
+    fs, io,
+    os::unix::process::CommandExt,
+    process::{Child, Command, ExitStatus},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{game_config::ProcessCleanup, session_log::SessionLog, signals};
+
+// Time between sending SIGTERM and SIGKILL to leftover processes
+const KILL_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+struct ProcessInfo {
+    pid: i32,
+    name: String,
+}
+
+// The game started in its own process group. Steamrunner is made a child subreaper before the game
+// is spawned, so processes that the game forks and then abandons (like the Proton and Ubisoft/EA
+// launchers do) are reparented to steamrunner instead of init and can still be waited on.
+pub struct GameProcess {
+    child: Child,
+    // The processes of the game seen so far, so the ones it abandoned are still recognized after
+    // they were reparented to steamrunner
+    known: BTreeSet<i32>,
+}
+
+impl GameProcess {
+    pub fn spawn(command: &mut Command) -> io::Result<Self> {
+        unsafe {
+            libc::prctl(libc::PR_SET_CHILD_SUBREAPER, 1, 0, 0, 0);
+        }
+
+        let child = command.process_group(0).spawn()?;
+
+        Ok(Self {
+            child,
+            known: BTreeSet::new(),
+        })
+    }
+
+    pub fn pid(&self) -> i32 {
+        self.child.id() as i32
+    }
+
+    // Wait for the launched command, and then for every process started by it to exit. Signals
+    // received in the meantime are forwarded to the game, and if it doesn't stop within the
+    // configured time it is killed. The game is stopped the same way once the time returned by
+    // stop_at has passed.
+    pub fn wait(
+        &mut self,
+        cleanup: &ProcessCleanup,
+        stop_at: &dyn Fn() -> Option<Instant>,
+        log: &SessionLog,
+    ) -> io::Result<ExitStatus> {
+        let stop_timeout = Duration::from_secs(cleanup.stop_timeout);
+        let mut stop_requested_at: Option<Instant> = None;
+        let mut killed = false;
+
+        // Poll instead of blocking in wait(), which would not return when a signal arrives
+        let status = loop {
+            if let Some(status) = self.child.try_wait()? {
+                break status;
+            }
+            // Processes that move to a session of their own are only recognized as the game's
+            // while the process that started them is still around
+            self.processes();
+
+            self.forward_signals(log, &mut stop_requested_at);
+            self.stop_when_due(stop_at(), log, &mut stop_requested_at);
+            if let Some(stop_requested_at) = stop_requested_at {
+                if !killed && stop_requested_at.elapsed() >= stop_timeout {
+                    log.log(&format!(
+                        "The game did not stop within {} seconds, killing it",
+                        cleanup.stop_timeout
+                    ));
+                    self.signal(libc::SIGKILL);
+                    killed = true;
+                }
+            }
+
+            thread::sleep(Duration::from_millis(100));
+        };
+        log.log(&format!("Game command exited ({})", status));
+
+        let exited_at = Instant::now();
+        let mut terminated_at: Option<Instant> = None;
+        let mut waiting_logged = false;
+
+        loop {
+            self.reap_children();
+
+            let remaining = self.processes();
+            if remaining.is_empty() {
+                break;
+            }
+
+            let names: Vec<&str> = remaining.iter().map(|info| info.name.as_str()).collect();
+            if !waiting_logged {
+                log.log(&format!(
+                    "Waiting for remaining game processes: {}",
+                    names.join(", ")
+                ));
+                waiting_logged = true;
+            }
+
+            self.forward_signals(log, &mut stop_requested_at);
+            self.stop_when_due(stop_at(), log, &mut stop_requested_at);
+
+            match terminated_at {
+                Some(terminated_at) => {
+                    if terminated_at.elapsed() >= KILL_GRACE_PERIOD {
+                        log.log(&format!("Killing leftover processes: {}", names.join(", ")));
+                        signal_all(&remaining, libc::SIGKILL);
+                    }
+                }
+                None => {
+                    let only_stragglers = remaining
+                        .iter()
+                        .all(|info| cleanup.stragglers.contains(&info.name));
+                    let timed_out = cleanup.kill_leftovers_after > 0
+                        && exited_at.elapsed() >= Duration::from_secs(cleanup.kill_leftovers_after);
+                    let stop_timed_out = stop_requested_at.map_or(false, |stop_requested_at| {
+                        stop_requested_at.elapsed() >= stop_timeout
+                    });
+
+                    if only_stragglers || timed_out || stop_timed_out {
+                        log.log(&format!(
+                            "Terminating leftover processes: {}",
+                            names.join(", ")
+                        ));
+                        signal_all(&remaining, libc::SIGTERM);
+                        terminated_at = Some(Instant::now());
+                    }
+                }
+            }
+
+            thread::sleep(Duration::from_millis(100));
+        }
+
+        log.log("All game processes have exited");
+
+        Ok(status)
+    }
+
+    // Forward a signal steamrunner received to the game, remembering when the first one arrived
+    fn forward_signals(&mut self, log: &SessionLog, stop_requested_at: &mut Option<Instant>) {
+        if let Some(signal) = signals::take() {
+            log.log(&format!(
+                "Received signal {}, forwarding it to the game",
+                signal
+            ));
+            self.signal(signal);
+            stop_requested_at.get_or_insert_with(Instant::now);
+        }
+    }
+
+    // Ask the game to stop once stop_at has passed, unless that was already done
+    fn stop_when_due(
+        &mut self,
+        stop_at: Option<Instant>,
+        log: &SessionLog,
+        stop_requested_at: &mut Option<Instant>,
+    ) {
+        if stop_requested_at.is_none() && stop_at.map_or(false, |stop_at| Instant::now() >= stop_at)
+        {
+            log.log("Playtime is up, stopping the game");
+            self.signal(libc::SIGTERM);
+            *stop_requested_at = Some(Instant::now());
+        }
+    }
+
+    // Send a signal to the game's process group and to every process started by the game, as some
+    // of them might have moved to a process group of their own
+    fn signal(&mut self, signal: i32) {
+        unsafe {
+            libc::kill(-(self.child.id() as i32), signal);
+        }
+        let processes = self.processes();
+        signal_all(&processes, signal);
+    }
+
+    // Reap every child that has exited. Orphaned processes of the game become children of
+    // steamrunner, and once they are zombies they no longer show up as processes of the game. This
+    // only runs after the game command exited, when the hooks are not running either, so there are
+    // no other children that someone else waits for
+    fn reap_children(&self) {
+        let mut status = 0;
+        while unsafe { libc::waitpid(-1, &mut status, libc::WNOHANG) } > 0 {}
+    }
+
+    // The living processes of the game, and remember them for the next time
+    fn processes(&mut self) -> Vec<ProcessInfo> {
+        let processes = game_processes(self.child.id() as i32, &self.known);
+        self.known = processes.iter().map(|info| info.pid).collect();
+
+        processes
+    }
+}
+
+fn signal_all(processes: &[ProcessInfo], signal: i32) {
+    for process in processes {
+        unsafe {
+            libc::kill(process.pid, signal);
+        }
+    }
+}
+
+// Find the living processes of the game by walking the parent pids in /proc from steamrunner, as
+// abandoned processes are reparented to it. Other children of steamrunner, like pre-launch commands
+// that are still running, are left out: only the game's process group, processes seen as part of
+// the game before and everything they started count
+fn game_processes(game_pgid: i32, known: &BTreeSet<i32>) -> Vec<ProcessInfo> {
+    let mut processes: Vec<(ProcessInfo, i32, i32)> = Vec::new();
+
+    for entry in fs::read_dir("/proc").into_iter().flatten().flatten() {
+        let pid: i32 = match entry.file_name().to_string_lossy().parse() {
+            Ok(pid) => pid,
+            Err(_) => continue,
+        };
+        let stat = match fs::read_to_string(entry.path().join("stat")) {
+            Ok(stat) => stat,
+            Err(_) => continue,
+        };
+
+        // The format is `pid (name) state ppid ...`, the name can contain spaces and parentheses
+        let (name_start, name_end) = match (stat.find('('), stat.rfind(')')) {
+            (Some(start), Some(end)) => (start, end),
+            _ => continue,
+        };
+        let mut fields = stat[name_end + 1..].split_whitespace();
+        let state = fields.next().unwrap_or("");
+        let ppid: i32 = fields
+            .next()
+            .and_then(|ppid| ppid.parse().ok())
+            .unwrap_or(0);
+        let pgid: i32 = fields
+            .next()
+            .and_then(|pgid| pgid.parse().ok())
+            .unwrap_or(0);
+
+        // Zombies are already gone, their parents just haven't reaped them yet
+        if state == "Z" {
+            continue;
+        }
+
+        processes.push((
+            ProcessInfo {
+                pid,
+                name: stat[name_start + 1..name_end].to_string(),
+            },
+            ppid,
+            pgid,
+        ));
+    }
+
+    // Parents to visit, and whether they belong to the game
+    let mut parents = vec![(std::process::id() as i32, false)];
+    let mut found = Vec::new();
+
+    while let Some((parent, parent_is_game)) = parents.pop() {
+        let mut index = 0;
+        while index < processes.len() {
+            if processes[index].1 == parent {
+                let (info, _, pgid) = processes.swap_remove(index);
+                let is_game = parent_is_game || pgid == game_pgid || known.contains(&info.pid);
+                parents.push((info.pid, is_game));
+                if is_game {
+                    found.push(info);
+                }
+            } else {
+                index += 1;
+            }
+        }
+    }
+
+    found
+}
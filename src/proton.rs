@@ -0,0 +1,146 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum DllOverrideMode {
+    Native,
+    Builtin,
+    NativeBuiltin,
+    BuiltinNative,
+    Disabled,
+}
+
+impl Default for DllOverrideMode {
+    fn default() -> Self {
+        DllOverrideMode::NativeBuiltin
+    }
+}
+
+impl DllOverrideMode {
+    // The mode as written in WINEDLLOVERRIDES, disabled DLLs have an empty mode
+    pub fn name(&self) -> &'static str {
+        match self {
+            DllOverrideMode::Native => "n",
+            DllOverrideMode::Builtin => "b",
+            DllOverrideMode::NativeBuiltin => "n,b",
+            DllOverrideMode::BuiltinNative => "b,n",
+            DllOverrideMode::Disabled => "",
+        }
+    }
+
+    // The mode as shown in the config editor
+    pub fn label(&self) -> &'static str {
+        match self {
+            DllOverrideMode::Native => "native",
+            DllOverrideMode::Builtin => "builtin",
+            DllOverrideMode::NativeBuiltin => "native, builtin",
+            DllOverrideMode::BuiltinNative => "builtin, native",
+            DllOverrideMode::Disabled => "disabled",
+        }
+    }
+
+    pub fn from_label(label: &str) -> Option<Self> {
+        match label {
+            "native" => Some(DllOverrideMode::Native),
+            "builtin" => Some(DllOverrideMode::Builtin),
+            "native, builtin" => Some(DllOverrideMode::NativeBuiltin),
+            "builtin, native" => Some(DllOverrideMode::BuiltinNative),
+            "disabled" => Some(DllOverrideMode::Disabled),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct DllOverride {
+    pub dll: String,
+    pub mode: DllOverrideMode,
+}
+
+// Proton and Wine settings turned into environment variables for Proton titles. Toggles that are
+// off and empty values leave the variable unset, so Proton uses its own default
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ProtonConfig {
+    // PROTON_LOG, writes steam-<appid>.log into the home directory
+    pub log: bool,
+    pub enable_nvapi: bool,
+    pub use_wined3d: bool,
+    // DXVK_HUD, like fps,devinfo
+    pub dxvk_hud: String,
+    pub dxvk_async: bool,
+    // VKD3D_CONFIG, like dxr11
+    pub vkd3d_config: String,
+    pub dll_overrides: Vec<DllOverride>,
+    // Options that only exist in dxvk.conf, like dxgi.maxFrameLatency or d3d11.samplerAnisotropy.
+    // D3D12 games read the dxgi options too, as DXVK provides their DXGI. VKD3D-Proton itself has
+    // no config file, so there is no VKD3D counterpart to this
+    pub dxvk_options: BTreeMap<String, String>,
+    // The dxvk.conf generated for this launch, which DXVK_CONFIG_FILE points at
+    #[serde(skip)]
+    pub dxvk_config_file: Option<String>,
+}
+
+impl ProtonConfig {
+    pub fn env(&self) -> BTreeMap<String, String> {
+        let mut env = BTreeMap::new();
+        let mut toggle = |name: &str, enabled: bool| {
+            if enabled {
+                env.insert(name.to_string(), "1".to_string());
+            }
+        };
+
+        toggle("PROTON_LOG", self.log);
+        toggle("PROTON_ENABLE_NVAPI", self.enable_nvapi);
+        toggle("PROTON_USE_WINED3D", self.use_wined3d);
+        toggle("DXVK_ASYNC", self.dxvk_async);
+
+        if !self.dxvk_hud.trim().is_empty() {
+            env.insert("DXVK_HUD".to_string(), self.dxvk_hud.trim().to_string());
+        }
+        if !self.vkd3d_config.trim().is_empty() {
+            env.insert(
+                "VKD3D_CONFIG".to_string(),
+                self.vkd3d_config.trim().to_string(),
+            );
+        }
+
+        if let Some(dxvk_config_file) = &self.dxvk_config_file {
+            env.insert("DXVK_CONFIG_FILE".to_string(), dxvk_config_file.clone());
+        }
+
+        let overrides: Vec<String> = self
+            .dll_overrides
+            .iter()
+            .filter(|dll_override| !dll_override.dll.trim().is_empty())
+            .map(|dll_override| format!("{}={}", dll_override.dll.trim(), dll_override.mode.name()))
+            .collect();
+        if !overrides.is_empty() {
+            env.insert("WINEDLLOVERRIDES".to_string(), overrides.join(";"));
+        }
+
+        env
+    }
+
+    // The contents of the generated dxvk.conf, None when there are no options to write
+    pub fn dxvk_config(&self) -> Option<String> {
+        if self.dxvk_options.is_empty() {
+            return None;
+        }
+
+        Some(
+            self.dxvk_options
+                .iter()
+                .map(|(key, value)| format!("{} = {}\n", key, value))
+                .collect(),
+        )
+    }
+}
+
+// Whether the command Steam passes in starts the game through Proton, or a compatibility tool
+// based on it. Native titles don't run a `proton` script
+pub fn is_proton_command(command: &str) -> bool {
+    command
+        .split_whitespace()
+        .any(|word| word == "proton" || word.ends_with("/proton"))
+}
@@ -0,0 +1,321 @@
+use std::{collections::BTreeMap, mem, os::unix::process::CommandExt, process::Command};
+
+use serde::{Deserialize, Serialize};
+
+use crate::session_log::SessionLog;
+
+const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum IoClass {
+    // Leave the I/O scheduling class as inherited from steamrunner
+    Default,
+    Realtime,
+    BestEffort,
+    Idle,
+}
+
+impl Default for IoClass {
+    fn default() -> Self {
+        IoClass::Default
+    }
+}
+
+impl IoClass {
+    pub fn name(&self) -> &'static str {
+        match self {
+            IoClass::Default => "default",
+            IoClass::Realtime => "realtime",
+            IoClass::BestEffort => "best-effort",
+            IoClass::Idle => "idle",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "default" => Some(IoClass::Default),
+            "realtime" => Some(IoClass::Realtime),
+            "best-effort" => Some(IoClass::BestEffort),
+            "idle" => Some(IoClass::Idle),
+            _ => None,
+        }
+    }
+
+    // The class as used by ioprio_set
+    fn value(&self) -> libc::c_int {
+        match self {
+            IoClass::Default => 0,
+            IoClass::Realtime => 1,
+            IoClass::BestEffort => 2,
+            IoClass::Idle => 3,
+        }
+    }
+}
+
+// Scheduling settings applied to the game process before it runs the launch command, so everything
+// the game starts inherits them, including processes that are reparented later on
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SchedulingConfig {
+    // Niceness from -20 (highest priority) to 19, going below 0 needs CAP_SYS_NICE
+    pub nice: Option<i32>,
+    // CPUs the game may run on, like 0-3,8, empty for all of them
+    pub cpu_affinity: String,
+    pub io_class: IoClass,
+    // Priority within the realtime and best-effort I/O classes, from 0 (highest) to 7
+    pub io_priority: Option<i32>,
+    // Resource limits by name, like nofile for esync. Set as the soft limit, raising the hard limit
+    // when needed
+    pub rlimits: BTreeMap<String, u64>,
+}
+
+impl SchedulingConfig {
+    // Problems that prevent using these settings on a machine with cpu_count CPUs
+    pub fn validate(&self, cpu_count: usize) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        if let Some(nice) = self.nice {
+            if !(-20..=19).contains(&nice) {
+                problems.push(format!("Nice level {} is not between -20 and 19", nice));
+            }
+        }
+        match parse_cpu_list(&self.cpu_affinity) {
+            Ok(cpus) => {
+                if let Some(cpu) = cpus.iter().find(|cpu| **cpu >= cpu_count) {
+                    problems.push(format!(
+                        "CPU {} does not exist, this machine has {} CPUs",
+                        cpu, cpu_count
+                    ));
+                }
+            }
+            Err(why) => problems.push(why),
+        }
+        if let Some(io_priority) = self.io_priority {
+            if !(0..=7).contains(&io_priority) {
+                problems.push(format!(
+                    "I/O priority {} is not between 0 and 7",
+                    io_priority
+                ));
+            }
+            if self.io_class == IoClass::Default || self.io_class == IoClass::Idle {
+                problems.push(
+                    "The I/O priority only applies to the realtime and best-effort classes"
+                        .to_string(),
+                );
+            }
+        }
+        for name in self.rlimits.keys() {
+            if rlimit_resource(name).is_none() {
+                problems.push(format!("Unknown resource limit {}", name));
+            }
+        }
+
+        problems
+    }
+
+    // Apply the settings in the child process between fork and exec. Settings that can't be
+    // applied are skipped rather than failing the launch, log_applied reports them afterwards
+    pub fn apply_on_exec<'a>(&self, command: &'a mut Command) -> &'a mut Command {
+        let nice = self.nice;
+        let cpu_set = parse_cpu_list(&self.cpu_affinity)
+            .ok()
+            .filter(|cpus| !cpus.is_empty())
+            .map(|cpus| {
+                let mut cpu_set: libc::cpu_set_t = unsafe { mem::zeroed() };
+                for cpu in cpus {
+                    unsafe { libc::CPU_SET(cpu, &mut cpu_set) };
+                }
+                cpu_set
+            });
+        let ioprio = self.ioprio();
+        let rlimits: Vec<_> = self
+            .rlimits
+            .iter()
+            .filter_map(|(name, limit)| rlimit_resource(name).map(|resource| (resource, *limit)))
+            .collect();
+
+        // Only async-signal-safe calls are made here, everything else was prepared above
+        unsafe {
+            command.pre_exec(move || {
+                if let Some(nice) = nice {
+                    libc::setpriority(libc::PRIO_PROCESS, 0, nice);
+                }
+                if let Some(cpu_set) = &cpu_set {
+                    libc::sched_setaffinity(0, mem::size_of::<libc::cpu_set_t>(), cpu_set);
+                }
+                if let Some(ioprio) = ioprio {
+                    libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, ioprio);
+                }
+                for (resource, limit) in &rlimits {
+                    let mut rlimit: libc::rlimit = mem::zeroed();
+                    libc::getrlimit(*resource, &mut rlimit);
+                    rlimit.rlim_cur = *limit;
+                    rlimit.rlim_max = rlimit.rlim_max.max(*limit);
+                    libc::setrlimit(*resource, &rlimit);
+                }
+                Ok(())
+            })
+        }
+    }
+
+    // Read the settings back from the started game process and log whether they were applied
+    pub fn log_applied(&self, pid: i32, log: &SessionLog) {
+        let check = |setting: String, applied: bool| {
+            log.log(&format!(
+                "{} {}",
+                setting,
+                if applied {
+                    "applied"
+                } else {
+                    "could not be applied"
+                }
+            ));
+        };
+
+        if let Some(nice) = self.nice {
+            let current = unsafe { libc::getpriority(libc::PRIO_PROCESS, pid as libc::id_t) };
+            check(format!("Nice level {}", nice), current == nice);
+        }
+        if let Ok(cpus) = parse_cpu_list(&self.cpu_affinity) {
+            if !cpus.is_empty() {
+                let mut cpu_set: libc::cpu_set_t = unsafe { mem::zeroed() };
+                let applied = unsafe {
+                    libc::sched_getaffinity(pid, mem::size_of::<libc::cpu_set_t>(), &mut cpu_set)
+                        == 0
+                        && (0..libc::CPU_SETSIZE as usize)
+                            .all(|cpu| libc::CPU_ISSET(cpu, &cpu_set) == cpus.contains(&cpu))
+                };
+                check(format!("CPU affinity {}", self.cpu_affinity), applied);
+            }
+        }
+        if let Some(ioprio) = self.ioprio() {
+            let current =
+                unsafe { libc::syscall(libc::SYS_ioprio_get, IOPRIO_WHO_PROCESS, pid) } as i32;
+            check(
+                format!("I/O class {}", self.io_class.name()),
+                current == ioprio,
+            );
+        }
+        for (name, limit) in &self.rlimits {
+            if let Some(resource) = rlimit_resource(name) {
+                let mut rlimit: libc::rlimit = unsafe { mem::zeroed() };
+                let applied = unsafe {
+                    libc::prlimit(pid, resource, std::ptr::null(), &mut rlimit) == 0
+                        && rlimit.rlim_cur == *limit
+                };
+                check(format!("Resource limit {}={}", name, limit), applied);
+            }
+        }
+    }
+
+    fn ioprio(&self) -> Option<libc::c_int> {
+        match self.io_class {
+            IoClass::Default => None,
+            IoClass::Idle => Some(IoClass::Idle.value() << IOPRIO_CLASS_SHIFT),
+            class => Some(class.value() << IOPRIO_CLASS_SHIFT | self.io_priority.unwrap_or(4)),
+        }
+    }
+}
+
+// Parse a CPU list like 0-3,8 into the CPUs it contains. CPUs past the size of a cpu_set_t are
+// rejected before a range is expanded, so a typo can't make it allocate billions of entries
+pub fn parse_cpu_list(list: &str) -> Result<Vec<usize>, String> {
+    let mut cpus = Vec::new();
+
+    for part in list
+        .split(',')
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+    {
+        let number = |value: &str| {
+            let cpu = value
+                .trim()
+                .parse::<usize>()
+                .map_err(|_| format!("Invalid CPU list {}", list))?;
+            if cpu >= libc::CPU_SETSIZE as usize {
+                return Err(format!(
+                    "CPU {} is out of range, CPUs go up to {}",
+                    cpu,
+                    libc::CPU_SETSIZE - 1
+                ));
+            }
+            Ok(cpu)
+        };
+        match part.split_once('-') {
+            Some((first, last)) => {
+                let (first, last) = (number(first)?, number(last)?);
+                if first > last {
+                    return Err(format!("Invalid CPU range {}", part));
+                }
+                cpus.extend(first..=last);
+            }
+            None => cpus.push(number(part)?),
+        }
+    }
+
+    Ok(cpus)
+}
+
+pub fn cpu_count() -> usize {
+    let count = unsafe { libc::sysconf(libc::_SC_NPROCESSORS_CONF) };
+    if count > 0 {
+        count as usize
+    } else {
+        1
+    }
+}
+
+fn rlimit_resource(name: &str) -> Option<libc::__rlimit_resource_t> {
+    match name {
+        "nofile" => Some(libc::RLIMIT_NOFILE),
+        "memlock" => Some(libc::RLIMIT_MEMLOCK),
+        "nproc" => Some(libc::RLIMIT_NPROC),
+        "core" => Some(libc::RLIMIT_CORE),
+        "stack" => Some(libc::RLIMIT_STACK),
+        "rtprio" => Some(libc::RLIMIT_RTPRIO),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cpu_lists() {
+        assert_eq!(parse_cpu_list(""), Ok(vec![]));
+        assert_eq!(parse_cpu_list("3"), Ok(vec![3]));
+        assert_eq!(parse_cpu_list("0-3,8"), Ok(vec![0, 1, 2, 3, 8]));
+        assert_eq!(parse_cpu_list(" 0 - 1 , 4 ,"), Ok(vec![0, 1, 4]));
+        assert_eq!(parse_cpu_list("2-2"), Ok(vec![2]));
+    }
+
+    #[test]
+    fn invalid_cpu_lists() {
+        assert_eq!(parse_cpu_list("a"), Err("Invalid CPU list a".to_string()));
+        assert_eq!(parse_cpu_list("0-"), Err("Invalid CPU list 0-".to_string()));
+        assert_eq!(parse_cpu_list("-1"), Err("Invalid CPU list -1".to_string()));
+        assert_eq!(
+            parse_cpu_list("3-1"),
+            Err("Invalid CPU range 3-1".to_string())
+        );
+    }
+
+    #[test]
+    fn cpus_past_cpu_setsize() {
+        let last = libc::CPU_SETSIZE as usize - 1;
+        assert_eq!(parse_cpu_list(&last.to_string()), Ok(vec![last]));
+
+        let out_of_range = format!("CPU {} is out of range, CPUs go up to {}", last + 1, last);
+        assert_eq!(
+            parse_cpu_list(&(last + 1).to_string()),
+            Err(out_of_range.clone())
+        );
+        // Rejected before the range is expanded
+        assert_eq!(
+            parse_cpu_list(&format!("0-{}", last + 1)),
+            Err(out_of_range)
+        );
+        assert!(parse_cpu_list("0-4000000000").is_err());
+    }
+}
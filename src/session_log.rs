@@ -0,0 +1,42 @@
+use std::{
+    fs::File,
+    io::Write,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+// Log of everything steamrunner itself does during one launch of a game, written to
+// logs/<appid>_session.log in the config directory. The game output is logged separately with --log
+pub struct SessionLog {
+    file: Mutex<Option<File>>,
+}
+
+impl SessionLog {
+    // Create (or truncate) the session log in the specified path. Failing to create the file
+    // only disables logging, as it should never prevent the game from starting
+    pub fn new(path: &String) -> Self {
+        let file = match File::create(path) {
+            Ok(file) => Some(file),
+            Err(why) => {
+                println!("Failed to create session log {}: {}", path, why);
+                None
+            }
+        };
+
+        Self {
+            file: Mutex::new(file),
+        }
+    }
+
+    // Write a single line prefixed with the unix timestamp into the log
+    pub fn log(&self, message: &str) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs_f64())
+            .unwrap_or(0.0);
+
+        if let Some(file) = &mut *self.file.lock().unwrap() {
+            writeln!(file, "[{:.3}] {}", timestamp, message).unwrap_or(());
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use std::{
+    env,
+    fs::{self, File},
+    io,
+    os::unix::io::AsRawFd,
+    process,
+};
+
+use crate::{journal, session_log::SessionLog};
+
+// Steamrunner's dir in the runtime dir, which only lasts until the user logs out or reboots
+pub fn runtime_dir() -> String {
+    match env::var("XDG_RUNTIME_DIR") {
+        Ok(runtime_dir) => format!("{}/steamrunner", runtime_dir),
+        Err(_) => format!("/tmp/steamrunner-{}", unsafe { libc::getuid() }),
+    }
+}
+
+// Shared actions are set up by the first running session that needs them and undone by the last
+// one. The sessions holding each action are kept in shared/<name> in the runtime dir, one pid per
+// line, and changed only while holding shared.lock
+fn shared_dir() -> String {
+    format!("{}/shared", runtime_dir())
+}
+
+// Exclusive lock on the shared state, released when the returned file is dropped
+fn lock(dir: &str) -> io::Result<File> {
+    fs::create_dir_all(dir)?;
+    let file = File::create(format!("{}.lock", dir))?;
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(file)
+}
+
+// Actions are keyed by name, which can be anything, so only keep the characters that are safe in
+// a file name
+fn state_path(dir: &str, name: &str) -> String {
+    let key: String = name
+        .chars()
+        .map(|chr| {
+            if chr.is_alphanumeric() || chr == '-' || chr == '.' {
+                chr
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("{}/{}", dir, key)
+}
+
+// The sessions holding an action, leaving out the ones that are no longer running
+fn holders(path: &str) -> Vec<u32> {
+    fs::read_to_string(path)
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| line.trim().parse().ok())
+        .filter(|pid| journal::is_running(*pid))
+        .collect()
+}
+
+fn write_holders(path: &str, holders: &[u32]) {
+    if holders.is_empty() {
+        fs::remove_file(path).unwrap_or(());
+        return;
+    }
+
+    let contents: String = holders.iter().map(|pid| format!("{}\n", pid)).collect();
+    if let Err(why) = fs::write(path, contents) {
+        println!("Failed to write shared action state {}: {}", path, why);
+    }
+}
+
+// Hold the shared action for this session, running setup if no other session holds it. Returns
+// whether the action is set up, in which case it has to be released again. Without the lock
+// another session could set it up at the same time, so it is skipped instead
+pub fn acquire(name: &str, setup: impl FnOnce() -> bool, log: &SessionLog) -> bool {
+    let dir = shared_dir();
+    let _lock = match lock(&dir) {
+        Ok(lock) => lock,
+        Err(why) => {
+            log.log(&format!(
+                "Failed to lock the shared action state, skipping shared action {}: {}",
+                name, why
+            ));
+            return false;
+        }
+    };
+    let path = state_path(&dir, name);
+    let mut holders = holders(&path);
+
+    if holders.is_empty() {
+        if !setup() {
+            write_holders(&path, &holders);
+            return false;
+        }
+    } else {
+        log.log(&format!(
+            "Shared action {} is already set up by {} other session(s)",
+            name,
+            holders.len()
+        ));
+    }
+
+    holders.push(process::id());
+    write_holders(&path, &holders);
+    true
+}
+
+// Release the shared action held by this session or an interrupted one, running undo if no other
+// running session holds it anymore. Without the lock it is left set up, as other sessions may
+// still need it
+pub fn release(name: &str, undo: impl FnOnce(), log: &SessionLog) {
+    let dir = shared_dir();
+    let _lock = match lock(&dir) {
+        Ok(lock) => lock,
+        Err(why) => {
+            log.log(&format!(
+                "Failed to lock the shared action state, not undoing shared action {}: {}",
+                name, why
+            ));
+            return;
+        }
+    };
+    let path = state_path(&dir, name);
+    let holders: Vec<u32> = holders(&path)
+        .into_iter()
+        .filter(|pid| *pid != process::id())
+        .collect();
+
+    if holders.is_empty() {
+        undo();
+    } else {
+        log.log(&format!(
+            "Shared action {} is still held by {} other session(s), not undoing it",
+            name,
+            holders.len()
+        ));
+    }
+    write_holders(&path, &holders);
+}
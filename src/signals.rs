@@ -0,0 +1,34 @@
+use std::sync::atomic::{AtomicI32, Ordering};
+
+// The last termination signal received by steamrunner, 0 if there is none waiting to be handled
+static RECEIVED_SIGNAL: AtomicI32 = AtomicI32::new(0);
+
+extern "C" fn handle_signal(signal: libc::c_int) {
+    RECEIVED_SIGNAL.store(signal, Ordering::SeqCst);
+}
+
+// Catch the signals Steam and the desktop session use to stop a game, so that steamrunner can
+// forward them to the game and still run the post-exit commands instead of dying immediately
+pub fn install_handlers() {
+    for signal in [libc::SIGTERM, libc::SIGINT, libc::SIGHUP] {
+        unsafe {
+            libc::signal(
+                signal,
+                handle_signal as extern "C" fn(libc::c_int) as libc::sighandler_t,
+            );
+        }
+    }
+}
+
+// Whether a signal was received that hasn't been handled yet, without handling it
+pub fn pending() -> bool {
+    RECEIVED_SIGNAL.load(Ordering::SeqCst) != 0
+}
+
+// Return the received signal if there is one, and mark it as handled
+pub fn take() -> Option<i32> {
+    match RECEIVED_SIGNAL.swap(0, Ordering::SeqCst) {
+        0 => None,
+        signal => Some(signal),
+    }
+}
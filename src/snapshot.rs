@@ -0,0 +1,342 @@
+use std::{
+    collections::BTreeMap,
+    env,
+    ffi::OsString,
+    fs::{self, File},
+    io,
+    os::unix::fs as unix_fs,
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{session_log::SessionLog, time};
+
+// Directories copied into <config_dir>/backups/<appid>/<timestamp> before each launch, like save
+// folders or parts of a Wine prefix
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SnapshotConfig {
+    pub directories: Vec<String>,
+    // Number of snapshots to keep per game, the oldest ones are removed first
+    #[serde(default = "default_keep")]
+    pub keep: usize,
+}
+
+impl Default for SnapshotConfig {
+    fn default() -> Self {
+        Self {
+            directories: Vec::new(),
+            keep: default_keep(),
+        }
+    }
+}
+
+fn default_keep() -> usize {
+    10
+}
+
+// Written into every snapshot, the directory at index i is stored in the subdirectory named i
+#[derive(Serialize, Deserialize, Debug)]
+struct Manifest {
+    directories: Vec<String>,
+}
+
+const MANIFEST: &str = "snapshot.yaml";
+
+fn backups_dir(config_dir: &str, appid: &str) -> String {
+    format!("{}/backups/{}", config_dir, appid)
+}
+
+// Snapshot the configured directories that exist, and remove the snapshots beyond the retention
+// limit. Files that are unchanged since the previous snapshot are hard linked to it instead of
+// copied. Returns the name of the new snapshot, or of the previous one when nothing changed
+pub fn create(
+    config_dir: &str,
+    appid: &str,
+    config: &SnapshotConfig,
+    log: &SessionLog,
+) -> io::Result<String> {
+    let name = take(config_dir, appid, &config.directories, &|message| {
+        log.log(message)
+    })?;
+
+    for old in list(config_dir, appid)
+        .iter()
+        .rev()
+        .skip(config.keep.max(1))
+    {
+        log.log(&format!("Removing old snapshot {}", old));
+        fs::remove_dir_all(format!("{}/{}", backups_dir(config_dir, appid), old)).unwrap_or(());
+    }
+
+    Ok(name)
+}
+
+// Snapshot the directories that exist. A snapshot that is the same as the previous one is removed
+// again, so unchanged launches don't push the useful snapshots out. Returns the name of the
+// snapshot with the current contents
+fn take(
+    config_dir: &str,
+    appid: &str,
+    directories: &[String],
+    log: &dyn Fn(&str),
+) -> io::Result<String> {
+    let previous = list(config_dir, appid).pop();
+    // Never reuse an existing snapshot, its files may be hard linked from other snapshots
+    let timestamp = timestamp();
+    let mut name = timestamp.clone();
+    let mut suffix = 1;
+    while Path::new(&format!("{}/{}", backups_dir(config_dir, appid), name)).exists() {
+        // Zero padded so the names keep sorting in the order they were taken
+        name = format!("{}_{:03}", timestamp, suffix);
+        suffix += 1;
+    }
+    let snapshot_dir = format!("{}/{}", backups_dir(config_dir, appid), name);
+    fs::create_dir_all(&snapshot_dir)?;
+
+    let mut manifest = Manifest {
+        directories: Vec::new(),
+    };
+    let (mut copied, mut linked) = (0, 0);
+    for directory in directories.iter().map(|directory| expand_home(directory)) {
+        if !Path::new(&directory).is_dir() {
+            log(&format!(
+                "Not snapshotting {}, it does not exist",
+                directory
+            ));
+            continue;
+        }
+
+        let index = manifest.directories.len().to_string();
+        // The same directory in the previous snapshot, to hard link unchanged files to
+        let previous_dir = previous.as_ref().and_then(|previous| {
+            let previous_dir = format!("{}/{}", backups_dir(config_dir, appid), previous);
+            read_manifest(&previous_dir)
+                .ok()?
+                .directories
+                .iter()
+                .position(|other| *other == directory)
+                .map(|index| format!("{}/{}", previous_dir, index))
+        });
+        let (dir_copied, dir_linked) = copy_tree(
+            Path::new(&directory),
+            &Path::new(&snapshot_dir).join(&index),
+            previous_dir.as_deref().map(Path::new),
+        )?;
+        copied += dir_copied;
+        linked += dir_linked;
+        manifest.directories.push(directory);
+    }
+
+    if let Some(previous) = previous {
+        let previous_dir = format!("{}/{}", backups_dir(config_dir, appid), previous);
+        let unchanged = read_manifest(&previous_dir).map_or(false, |previous_manifest| {
+            previous_manifest.directories == manifest.directories
+                && (0..manifest.directories.len()).all(|index| {
+                    same_tree(
+                        &Path::new(&snapshot_dir).join(index.to_string()),
+                        &Path::new(&previous_dir).join(index.to_string()),
+                    )
+                })
+        });
+        if unchanged {
+            fs::remove_dir_all(&snapshot_dir)?;
+            log(&format!(
+                "Nothing changed since snapshot {}, not creating a new one",
+                previous
+            ));
+            return Ok(previous);
+        }
+    }
+
+    fs::write(
+        format!("{}/{}", snapshot_dir, MANIFEST),
+        serde_yaml::to_string(&manifest).unwrap(),
+    )?;
+    log(&format!(
+        "Created snapshot {} of {} directories ({} files copied, {} unchanged files linked)",
+        name,
+        manifest.directories.len(),
+        copied,
+        linked
+    ));
+
+    Ok(name)
+}
+
+// The snapshots of a game from oldest to newest
+pub fn list(config_dir: &str, appid: &str) -> Vec<String> {
+    let mut snapshots: Vec<String> = fs::read_dir(backups_dir(config_dir, appid))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|entry| entry.path().join(MANIFEST).is_file())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .collect();
+    snapshots.sort();
+
+    snapshots
+}
+
+// Replace the directories in a snapshot with their contents at the time it was taken. The current
+// contents are snapshotted first, and every directory is copied next to the live one before it is
+// swapped in, so a failed restore leaves it as it was. Returns the restored directories
+pub fn restore(config_dir: &str, appid: &str, snapshot: &str) -> Result<Vec<String>, String> {
+    // Only names of existing snapshots, so the name can't point outside of the backups
+    if !list(config_dir, appid).iter().any(|name| name == snapshot) {
+        return Err(format!("Snapshot {} of {} not found", snapshot, appid));
+    }
+    let snapshot_dir = format!("{}/{}", backups_dir(config_dir, appid), snapshot);
+    let manifest = read_manifest(&snapshot_dir)
+        .map_err(|why| format!("Snapshot {} of {} can't be read: {}", snapshot, appid, why))?;
+
+    let current = take(config_dir, appid, &manifest.directories, &|message| {
+        println!("{}", message)
+    })
+    .map_err(|why| format!("Failed to snapshot the current state: {}", why))?;
+    println!("The current state is kept as snapshot {}", current);
+
+    for (index, directory) in manifest.directories.iter().enumerate() {
+        let restoring = format!("{}.steamrunner-restoring", directory);
+        let replaced = format!("{}.steamrunner-replaced", directory);
+        for leftover in [&restoring, &replaced] {
+            if Path::new(leftover).exists() {
+                fs::remove_dir_all(leftover)
+                    .map_err(|why| format!("Failed to remove {}: {}", leftover, why))?;
+            }
+        }
+
+        // Copied without linking, so changes to the restored files can't reach the snapshot
+        if let Err(why) = copy_tree(
+            &Path::new(&snapshot_dir).join(index.to_string()),
+            Path::new(&restoring),
+            None,
+        ) {
+            fs::remove_dir_all(&restoring).unwrap_or(());
+            return Err(format!("Failed to restore {}: {}", directory, why));
+        }
+        if Path::new(directory).exists() {
+            fs::rename(directory, &replaced)
+                .map_err(|why| format!("Failed to move {} aside: {}", directory, why))?;
+        }
+        if let Err(why) = fs::rename(&restoring, directory) {
+            fs::rename(&replaced, directory).unwrap_or(());
+            return Err(format!("Failed to restore {}: {}", directory, why));
+        }
+        fs::remove_dir_all(&replaced).unwrap_or(());
+    }
+
+    Ok(manifest.directories)
+}
+
+fn read_manifest(snapshot_dir: &str) -> Result<Manifest, String> {
+    File::open(format!("{}/{}", snapshot_dir, MANIFEST))
+        .map_err(|why| why.to_string())
+        .and_then(|file| serde_yaml::from_reader(file).map_err(|why| why.to_string()))
+}
+
+// Copy a directory tree, keeping modification times and symlinks. Files with the same size and
+// modification time in the previous copy are hard linked to it. Returns the number of files copied
+// and linked
+fn copy_tree(source: &Path, target: &Path, previous: Option<&Path>) -> io::Result<(usize, usize)> {
+    fs::create_dir_all(target)?;
+    let (mut copied, mut linked) = (0, 0);
+
+    for entry in fs::read_dir(source)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let target_path = target.join(entry.file_name());
+        let previous_path = previous.map(|previous| previous.join(entry.file_name()));
+
+        if file_type.is_dir() {
+            let (dir_copied, dir_linked) =
+                copy_tree(&entry.path(), &target_path, previous_path.as_deref())?;
+            copied += dir_copied;
+            linked += dir_linked;
+        } else if file_type.is_symlink() {
+            unix_fs::symlink(fs::read_link(entry.path())?, &target_path)?;
+        } else if file_type.is_file() {
+            let metadata = entry.metadata()?;
+            let unchanged = previous_path
+                .as_ref()
+                .and_then(|previous_path| fs::symlink_metadata(previous_path).ok())
+                .map_or(false, |previous| {
+                    previous.is_file()
+                        && previous.len() == metadata.len()
+                        && previous.modified().ok() == metadata.modified().ok()
+                });
+
+            if unchanged && fs::hard_link(previous_path.unwrap(), &target_path).is_ok() {
+                linked += 1;
+            } else {
+                fs::copy(entry.path(), &target_path)?;
+                File::options()
+                    .write(true)
+                    .open(&target_path)?
+                    .set_modified(metadata.modified()?)?;
+                copied += 1;
+            }
+        }
+    }
+
+    Ok((copied, linked))
+}
+
+// Whether two directory trees have the same entries, with files of the same size and modification
+// time and symlinks to the same targets
+fn same_tree(first: &Path, second: &Path) -> bool {
+    let entries = |dir: &Path| -> Option<BTreeMap<OsString, fs::Metadata>> {
+        fs::read_dir(dir)
+            .ok()?
+            .map(|entry| {
+                let entry = entry.ok()?;
+                Some((entry.file_name(), fs::symlink_metadata(entry.path()).ok()?))
+            })
+            .collect()
+    };
+    let (first_entries, second_entries) = match (entries(first), entries(second)) {
+        (Some(first_entries), Some(second_entries)) => (first_entries, second_entries),
+        _ => return false,
+    };
+    if !first_entries.keys().eq(second_entries.keys()) {
+        return false;
+    }
+
+    first_entries.iter().all(|(name, metadata)| {
+        let other = &second_entries[name];
+        let (first_path, second_path) = (first.join(name), second.join(name));
+        if metadata.is_dir() && other.is_dir() {
+            same_tree(&first_path, &second_path)
+        } else if metadata.file_type().is_symlink() && other.file_type().is_symlink() {
+            fs::read_link(first_path).ok() == fs::read_link(second_path).ok()
+        } else {
+            metadata.is_file()
+                && other.is_file()
+                && metadata.len() == other.len()
+                && metadata.modified().ok() == other.modified().ok()
+        }
+    })
+}
+
+// Expand a leading ~/ to the home directory
+pub fn expand_home(path: &str) -> String {
+    match path.strip_prefix("~/") {
+        Some(rest) => format!("{}/{}", env::var("HOME").unwrap_or_default(), rest),
+        None => path.to_string(),
+    }
+}
+
+// The time in UTC as YYYY-MM-DD_HH-MM-SS, which sorts in the order the snapshots were taken. Local
+// time would not, as it jumps back when daylight saving time ends or the time zone changes
+fn timestamp() -> String {
+    let time = time::utc_time(time::now());
+    format!(
+        "{:04}-{:02}-{:02}_{:02}-{:02}-{:02}",
+        time.tm_year + 1900,
+        time.tm_mon + 1,
+        time.tm_mday,
+        time.tm_hour,
+        time.tm_min,
+        time.tm_sec
+    )
+}
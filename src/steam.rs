@@ -0,0 +1,126 @@
+use std::{env, fs};
+
+use notify_rust::Notification;
+
+use crate::{session_log::SessionLog, GameConfig};
+
+// A change of the build id since the previous launch, which means Steam updated the game
+pub struct GameUpdate {
+    pub previous: String,
+    pub current: String,
+}
+
+// The Steam directory, which Steam passes to the games it launches
+fn steam_root() -> String {
+    env::var("STEAM_COMPAT_CLIENT_INSTALL_PATH")
+        .unwrap_or(format!("{}/.local/share/Steam", env::var("HOME").unwrap()))
+}
+
+// A "key" "value" line of a vdf or acf file
+fn key_value(line: &str) -> Option<(&str, &str)> {
+    let mut parts = line.split('"').filter(|part| !part.trim().is_empty());
+    Some((parts.next()?, parts.next()?))
+}
+
+// The Steam dir and the other library folders listed in its libraryfolders.vdf
+fn library_folders(steam_root: &str) -> Vec<String> {
+    let mut folders = vec![steam_root.to_string()];
+
+    let library_folders =
+        fs::read_to_string(format!("{}/steamapps/libraryfolders.vdf", steam_root))
+            .unwrap_or_default();
+    for (_, path) in library_folders
+        .lines()
+        .filter_map(key_value)
+        .filter(|(key, _)| *key == "path")
+    {
+        if !folders.iter().any(|folder| folder == path) {
+            folders.push(path.to_string());
+        }
+    }
+
+    folders
+}
+
+// The library folder the game is installed in and the contents of its appmanifest
+fn app_manifest(appid: &str) -> Option<(String, String)> {
+    library_folders(&steam_root())
+        .into_iter()
+        .find_map(|folder| {
+            let manifest =
+                fs::read_to_string(format!("{}/steamapps/appmanifest_{}.acf", folder, appid))
+                    .ok()?;
+            Some((folder, manifest))
+        })
+}
+
+fn manifest_value(manifest: &str, name: &str) -> Option<String> {
+    manifest
+        .lines()
+        .filter_map(key_value)
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.to_string())
+}
+
+pub fn build_id(appid: &str) -> Option<String> {
+    manifest_value(&app_manifest(appid)?.1, "buildid")
+}
+
+// The directory the game is installed in, steamapps/common/<installdir> of its library
+pub fn install_dir(appid: &str) -> Option<String> {
+    let (folder, manifest) = app_manifest(appid)?;
+    manifest_value(&manifest, "installdir")
+        .map(|install_dir| format!("{}/steamapps/common/{}", folder, install_dir))
+}
+
+// Compare the build id with the one seen on the previous launch and save the current one in the
+// game config. The first build id seen is only recorded
+pub fn detect_update(
+    appid: &str,
+    config: &mut GameConfig,
+    config_path: &String,
+    log: &SessionLog,
+) -> Option<GameUpdate> {
+    let current = build_id(appid)?;
+    if current == config.last_build_id {
+        return None;
+    }
+
+    let previous = std::mem::replace(&mut config.last_build_id, current.clone());
+    config.save(config_path);
+    if previous.is_empty() {
+        log.log(&format!("Recorded build id {}", current));
+        return None;
+    }
+
+    log.log(&format!(
+        "The game was updated from build {} to {}",
+        previous, current
+    ));
+    Some(GameUpdate { previous, current })
+}
+
+// Without the gui the update is announced with a notification, which offers to launch in safe
+// mode. Returns whether that was chosen
+pub fn ask_safe_mode(update: &GameUpdate, appid: &str) -> bool {
+    let handle = match Notification::new()
+        .summary("Game updated")
+        .body(&format!(
+            "{} was updated from build {} to {}, the tweaks made for it may no longer work",
+            appid, update.previous, update.current
+        ))
+        .action("safe", "Start in safe mode")
+        .action("normal", "Start normally")
+        .show()
+    {
+        Ok(handle) => handle,
+        Err(why) => {
+            println!("Failed to show notification: {}", why);
+            return false;
+        }
+    };
+
+    let mut safe_mode = false;
+    handle.wait_for_action(|action| safe_mode = action == "safe");
+    safe_mode
+}
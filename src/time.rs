@@ -0,0 +1,53 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Seconds since the epoch
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+// Seconds since the epoch as local time
+pub fn local_time(time: u64) -> libc::tm {
+    let time = time as libc::time_t;
+    unsafe {
+        let mut local: libc::tm = std::mem::zeroed();
+        libc::localtime_r(&time, &mut local);
+        local
+    }
+}
+
+// Seconds since the epoch as UTC
+pub fn utc_time(time: u64) -> libc::tm {
+    let time = time as libc::time_t;
+    unsafe {
+        let mut utc: libc::tm = std::mem::zeroed();
+        libc::gmtime_r(&time, &mut utc);
+        utc
+    }
+}
+
+// The start of the local day the time falls on, in seconds since the epoch
+pub fn start_of_day(time: u64) -> u64 {
+    let mut local = local_time(time);
+    local.tm_hour = 0;
+    local.tm_min = 0;
+    local.tm_sec = 0;
+    // Let mktime work out whether daylight saving time was in effect at midnight
+    local.tm_isdst = -1;
+    unsafe { libc::mktime(&mut local) as u64 }
+}
+
+// A local time as YYYY-MM-DD HH:MM
+pub fn format_local(time: u64) -> String {
+    let local = local_time(time);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        local.tm_year + 1900,
+        local.tm_mon + 1,
+        local.tm_mday,
+        local.tm_hour,
+        local.tm_min
+    )
+}
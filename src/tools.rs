@@ -0,0 +1,32 @@
+use crate::{condition, hooks};
+
+// The program a replace_with value runs, skipping leading environment variable assignments like
+// `DXVK_HUD=1`. Values that only set variables don't need any tool
+pub fn tool_name(replace_with: &str) -> Option<String> {
+    hooks::split_words(replace_with)
+        .into_iter()
+        .find(|word| !is_assignment(word))
+}
+
+// Short description of the availability of the tool for the config editor
+pub fn describe(replace_with: &str) -> String {
+    match tool_name(replace_with) {
+        Some(tool) => {
+            if condition::find_in_path(&tool).is_some() {
+                "Available".to_string()
+            } else {
+                format!("Missing: {}", tool)
+            }
+        }
+        None => String::new(),
+    }
+}
+
+fn is_assignment(word: &str) -> bool {
+    match word.split_once('=') {
+        Some((name, _)) => {
+            !name.is_empty() && name.chars().all(|chr| chr.is_alphanumeric() || chr == '_')
+        }
+        None => false,
+    }
+}
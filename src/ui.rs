@@ -1,17 +1,51 @@
 sixtyfps::include_modules!();
 
 use std::{
+    cell::RefCell,
+    collections::BTreeMap,
     env,
     path::Path,
+    rc::Rc,
     sync::{Arc, Mutex},
 };
 
 use crate::{
-    game_config::{ConfigCommand, ConfigOption},
-    GameConfig,
+    catalogue::CatalogueEntry,
+    condition,
+    frequency::{self, Frequency},
+    game_config::{ConfigCommand, ConfigOption, PairedAction, SafeModeConfig, Shell},
+    gamescope::{GamescopeConfig, GamescopeFilter},
+    gpu::Gpu,
+    journal::Journal,
+    mangohud::MangoHudConfig,
+    overlay::FileOverlay,
+    playtime::PlaytimeLimits,
+    preflight::{self, PreflightCheck, PreflightKind},
+    proton::{DllOverride, DllOverrideMode, ProtonConfig},
+    scheduling::{self, IoClass, SchedulingConfig},
+    session_log::SessionLog,
+    snapshot::SnapshotConfig,
+    steam::GameUpdate,
+    tools, GameConfig,
 };
 use closure::closure;
-use sixtyfps::SharedString;
+use sixtyfps::{Model, SharedString};
+
+// How the Start Menu was left
+pub enum Launch {
+    Cancelled,
+    Normal,
+    SafeMode,
+}
+
+// The launch counters are left empty when they are not set
+fn next_launches_text(next_launches: u32) -> String {
+    if next_launches == 0 {
+        String::new()
+    } else {
+        next_launches.to_string()
+    }
+}
 
 impl From<ConfigOption> for sixtyfps_generated_Main::SixtyConfigOption {
     fn from(config: ConfigOption) -> Self {
@@ -20,6 +54,8 @@ impl From<ConfigOption> for sixtyfps_generated_Main::SixtyConfigOption {
             replace_with: SharedString::from(&config.replace_with),
             enabled: config.enabled,
             modified: config.modified,
+            condition: SharedString::from(&config.condition),
+            next_launches: SharedString::from(next_launches_text(config.next_launches)),
         }
     }
 }
@@ -31,6 +67,8 @@ impl From<SixtyConfigOption> for ConfigOption {
             replace_with: config.replace_with.into(),
             enabled: config.enabled,
             modified: config.modified,
+            condition: config.condition.into(),
+            next_launches: config.next_launches.trim().parse().unwrap_or(0),
         }
     }
 }
@@ -41,6 +79,16 @@ impl From<ConfigCommand> for sixtyfps_generated_Main::SixtyConfigCommand {
             command: SharedString::from(command.command),
             enabled: command.enabled,
             modified: command.modified,
+            name: SharedString::from(command.name),
+            parallel: command.parallel,
+            after: SharedString::from(command.after.join(", ")),
+            shell: SharedString::from(command.shell.name()),
+            working_dir: SharedString::from(command.working_dir),
+            env: env_model(command.env),
+            strip_steam_env: command.strip_steam_env,
+            condition: SharedString::from(command.condition),
+            frequency: SharedString::from(command.frequency.name()),
+            next_launches: SharedString::from(next_launches_text(command.next_launches)),
         }
     }
 }
@@ -51,16 +99,635 @@ impl From<SixtyConfigCommand> for ConfigCommand {
             command: command.command.into(),
             enabled: command.enabled,
             modified: command.modified,
+            name: command.name.into(),
+            parallel: command.parallel,
+            // The names of the commands to run after are entered as a comma separated list
+            after: command
+                .after
+                .split(',')
+                .map(|name| name.trim().to_string())
+                .filter(|name| !name.is_empty())
+                .collect(),
+            shell: Shell::from_name(&command.shell).unwrap_or_default(),
+            working_dir: command.working_dir.into(),
+            env: env_from_model(&command.env),
+            strip_steam_env: command.strip_steam_env,
+            condition: command.condition.into(),
+            frequency: Frequency::from_name(&command.frequency).unwrap_or_default(),
+            next_launches: command.next_launches.trim().parse().unwrap_or(0),
+        }
+    }
+}
+
+impl From<PairedAction> for sixtyfps_generated_Main::SixtyPairedAction {
+    fn from(action: PairedAction) -> Self {
+        Self {
+            name: SharedString::from(action.name),
+            setup: SharedString::from(action.setup),
+            undo: SharedString::from(action.undo),
+            enabled: action.enabled,
+            condition: SharedString::from(action.condition),
+            shared: action.shared,
+            shell: SharedString::from(action.shell.name()),
+            working_dir: SharedString::from(action.working_dir),
+            env: env_model(action.env),
+            strip_steam_env: action.strip_steam_env,
+        }
+    }
+}
+
+impl From<SixtyPairedAction> for PairedAction {
+    fn from(action: SixtyPairedAction) -> Self {
+        Self {
+            name: action.name.into(),
+            setup: action.setup.into(),
+            undo: action.undo.into(),
+            enabled: action.enabled,
+            condition: action.condition.into(),
+            shared: action.shared,
+            shell: Shell::from_name(&action.shell).unwrap_or_default(),
+            working_dir: action.working_dir.into(),
+            env: env_from_model(&action.env),
+            strip_steam_env: action.strip_steam_env,
+        }
+    }
+}
+
+// The environment variables of a command for the form, one row per variable
+fn env_model(env: BTreeMap<String, String>) -> sixtyfps::ModelHandle<SixtyEnvVar> {
+    sixtyfps::ModelHandle::new(std::rc::Rc::new(sixtyfps::VecModel::from(
+        env.into_iter()
+            .map(|(key, value)| SixtyEnvVar {
+                key: SharedString::from(key),
+                value: SharedString::from(value),
+            })
+            .collect::<Vec<SixtyEnvVar>>(),
+    )))
+}
+
+// Values are kept as entered, they may contain anything
+fn env_from_model(env: &sixtyfps::ModelHandle<SixtyEnvVar>) -> BTreeMap<String, String> {
+    (0..env.row_count())
+        .map(|row| env.row_data(row))
+        .filter(|env_var| !env_var.key.trim().is_empty())
+        .map(|env_var| (env_var.key.trim().to_string(), env_var.value.into()))
+        .collect()
+}
+
+impl From<FileOverlay> for sixtyfps_generated_Main::SixtyFileOverlay {
+    fn from(overlay: FileOverlay) -> Self {
+        Self {
+            source: SharedString::from(overlay.source),
+            target: SharedString::from(overlay.target),
+            enabled: overlay.enabled,
+        }
+    }
+}
+
+impl From<SixtyFileOverlay> for FileOverlay {
+    fn from(overlay: SixtyFileOverlay) -> Self {
+        Self {
+            source: overlay.source.into(),
+            target: overlay.target.into(),
+            enabled: overlay.enabled,
+        }
+    }
+}
+
+impl From<PreflightCheck> for sixtyfps_generated_Main::SixtyPreflightCheck {
+    fn from(check: PreflightCheck) -> Self {
+        Self {
+            kind: SharedString::from(check.kind.name()),
+            target: SharedString::from(check.target),
+            amount: SharedString::from(check.amount.to_string()),
+            enabled: check.enabled,
+        }
+    }
+}
+
+impl From<SixtyPreflightCheck> for PreflightCheck {
+    fn from(check: SixtyPreflightCheck) -> Self {
+        Self {
+            kind: PreflightKind::from_name(&check.kind).unwrap_or_default(),
+            target: check.target.into(),
+            amount: check.amount.trim().parse().unwrap_or(0),
+            enabled: check.enabled,
+        }
+    }
+}
+
+// The failed preflight checks that can be told without waiting, for the Start Menu
+fn preflight_notice(checks: &[PreflightCheck], install_dir: Option<&str>) -> SharedString {
+    let failures = preflight::check_now(checks, install_dir);
+    if failures.is_empty() {
+        SharedString::default()
+    } else {
+        SharedString::from(format!(
+            "Preflight checks failing, the game won't start: {}",
+            failures.join(". ")
+        ))
+    }
+}
+
+impl From<SafeModeConfig> for sixtyfps_generated_Main::SixtySafeModeConfig {
+    fn from(config: SafeModeConfig) -> Self {
+        Self {
+            options: config.options,
+            gamescope: config.gamescope,
+            mangohud: config.mangohud,
+            proton: config.proton,
+            overlays: config.overlays,
+            commands: config.commands,
+            scheduling: config.scheduling,
+            fast_failure: SharedString::from(config.fast_failure.to_string()),
+        }
+    }
+}
+
+impl From<SixtySafeModeConfig> for SafeModeConfig {
+    fn from(config: SixtySafeModeConfig) -> Self {
+        Self {
+            options: config.options,
+            gamescope: config.gamescope,
+            mangohud: config.mangohud,
+            proton: config.proton,
+            overlays: config.overlays,
+            commands: config.commands,
+            scheduling: config.scheduling,
+            fast_failure: config
+                .fast_failure
+                .trim()
+                .parse()
+                .unwrap_or(SafeModeConfig::default().fast_failure),
+        }
+    }
+}
+
+impl From<SnapshotConfig> for sixtyfps_generated_Main::SixtySnapshotConfig {
+    fn from(config: SnapshotConfig) -> Self {
+        Self {
+            directories: SharedString::from(config.directories.join("; ")),
+            keep: SharedString::from(config.keep.to_string()),
         }
     }
 }
 
+impl From<SixtySnapshotConfig> for SnapshotConfig {
+    fn from(config: SixtySnapshotConfig) -> Self {
+        Self {
+            directories: config
+                .directories
+                .split(';')
+                .map(|directory| directory.trim().to_string())
+                .filter(|directory| !directory.is_empty())
+                .collect(),
+            keep: config
+                .keep
+                .trim()
+                .parse()
+                .unwrap_or(SnapshotConfig::default().keep),
+        }
+    }
+}
+
+impl From<GamescopeConfig> for sixtyfps_generated_Main::SixtyGamescopeConfig {
+    fn from(config: GamescopeConfig) -> Self {
+        // Unset numbers are shown as empty fields
+        let number = |value: u32| {
+            if value == 0 {
+                SharedString::default()
+            } else {
+                SharedString::from(value.to_string())
+            }
+        };
+
+        Self {
+            enabled: config.enabled,
+            game_width: number(config.game_width),
+            game_height: number(config.game_height),
+            output_width: number(config.output_width),
+            output_height: number(config.output_height),
+            refresh_rate: number(config.refresh_rate),
+            fullscreen: config.fullscreen,
+            filter: SharedString::from(config.filter.name()),
+            sharpness: config
+                .sharpness
+                .map(|sharpness| SharedString::from(sharpness.to_string()))
+                .unwrap_or_default(),
+            hdr: config.hdr,
+            extra_args: SharedString::from(config.extra_args),
+        }
+    }
+}
+
+impl From<SixtyGamescopeConfig> for GamescopeConfig {
+    fn from(config: SixtyGamescopeConfig) -> Self {
+        let number = |value: &SharedString| value.trim().parse().unwrap_or(0);
+
+        Self {
+            enabled: config.enabled,
+            game_width: number(&config.game_width),
+            game_height: number(&config.game_height),
+            output_width: number(&config.output_width),
+            output_height: number(&config.output_height),
+            refresh_rate: number(&config.refresh_rate),
+            fullscreen: config.fullscreen,
+            filter: GamescopeFilter::from_name(config.filter.as_str()).unwrap_or_default(),
+            sharpness: config.sharpness.trim().parse().ok(),
+            hdr: config.hdr,
+            extra_args: config.extra_args.into(),
+        }
+    }
+}
+
+// Problems with the gamescope settings as entered in the form, including numbers that don't parse
+fn check_gamescope(config: SixtyGamescopeConfig, launch_command: &str) -> String {
+    let mut problems: Vec<String> = [
+        ("game width", &config.game_width),
+        ("game height", &config.game_height),
+        ("output width", &config.output_width),
+        ("output height", &config.output_height),
+        ("refresh rate", &config.refresh_rate),
+        ("sharpness", &config.sharpness),
+    ]
+    .iter()
+    .filter(|(_, value)| !value.trim().is_empty() && value.trim().parse::<u32>().is_err())
+    .map(|(name, value)| format!("Invalid {} {}", name, value))
+    .collect();
+
+    problems.extend(GamescopeConfig::from(config).validate(launch_command));
+    problems.join(". ")
+}
+
+impl From<MangoHudConfig> for sixtyfps_generated_Main::SixtyMangoHudConfig {
+    fn from(config: MangoHudConfig) -> Self {
+        Self {
+            enabled: SharedString::from(match config.enabled {
+                Some(true) => "on",
+                Some(false) => "off",
+                None => "default",
+            }),
+            fps_limit: config
+                .fps_limit
+                .map(|fps_limit| SharedString::from(fps_limit.to_string()))
+                .unwrap_or_default(),
+            position: SharedString::from(config.position.as_deref().unwrap_or("default")),
+            metrics: config
+                .metrics
+                .map(|metrics| SharedString::from(metrics.join(", ")))
+                .unwrap_or_default(),
+            log_folder: config
+                .log_folder
+                .map(SharedString::from)
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl From<SixtyMangoHudConfig> for MangoHudConfig {
+    fn from(config: SixtyMangoHudConfig) -> Self {
+        // Empty fields are left unset, so they fall back to the global config
+        let text = |value: &SharedString| {
+            if value.trim().is_empty() || value.as_str() == "default" {
+                None
+            } else {
+                Some(value.trim().to_string())
+            }
+        };
+
+        Self {
+            enabled: match config.enabled.as_str() {
+                "on" => Some(true),
+                "off" => Some(false),
+                _ => None,
+            },
+            fps_limit: config.fps_limit.trim().parse().ok(),
+            position: text(&config.position),
+            metrics: text(&config.metrics).map(|metrics| {
+                metrics
+                    .split(',')
+                    .map(|metric| metric.trim().to_string())
+                    .filter(|metric| !metric.is_empty())
+                    .collect()
+            }),
+            log_folder: text(&config.log_folder),
+        }
+    }
+}
+
+// Problems with the MangoHud settings as entered in the form
+fn check_mangohud(config: SixtyMangoHudConfig) -> String {
+    let mut problems = Vec::new();
+
+    if !config.fps_limit.trim().is_empty() && config.fps_limit.trim().parse::<u32>().is_err() {
+        problems.push(format!("Invalid FPS limit {}", config.fps_limit));
+    }
+    problems.extend(MangoHudConfig::from(config).validate());
+
+    problems.join(". ")
+}
+
+impl From<ProtonConfig> for sixtyfps_generated_Main::SixtyProtonConfig {
+    fn from(config: ProtonConfig) -> Self {
+        Self {
+            log: config.log,
+            enable_nvapi: config.enable_nvapi,
+            use_wined3d: config.use_wined3d,
+            dxvk_hud: SharedString::from(config.dxvk_hud),
+            dxvk_async: config.dxvk_async,
+            vkd3d_config: SharedString::from(config.vkd3d_config),
+            dxvk_options: SharedString::from(
+                config
+                    .dxvk_options
+                    .iter()
+                    .map(|(key, value)| format!("{} = {}", key, value))
+                    .collect::<Vec<String>>()
+                    .join("; "),
+            ),
+        }
+    }
+}
+
+// The DLL overrides are edited separately, so they are left empty
+impl From<SixtyProtonConfig> for ProtonConfig {
+    fn from(config: SixtyProtonConfig) -> Self {
+        Self {
+            log: config.log,
+            enable_nvapi: config.enable_nvapi,
+            use_wined3d: config.use_wined3d,
+            dxvk_hud: config.dxvk_hud.into(),
+            dxvk_async: config.dxvk_async,
+            vkd3d_config: config.vkd3d_config.into(),
+            dll_overrides: Vec::new(),
+            dxvk_options: config
+                .dxvk_options
+                .split(';')
+                .filter_map(|pair| pair.split_once('='))
+                .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+                .filter(|(key, _)| !key.is_empty())
+                .collect(),
+            dxvk_config_file: None,
+        }
+    }
+}
+
+impl From<DllOverride> for sixtyfps_generated_Main::SixtyDllOverride {
+    fn from(dll_override: DllOverride) -> Self {
+        Self {
+            dll: SharedString::from(dll_override.dll),
+            mode: SharedString::from(dll_override.mode.label()),
+        }
+    }
+}
+
+impl From<SixtyDllOverride> for DllOverride {
+    fn from(dll_override: SixtyDllOverride) -> Self {
+        Self {
+            dll: dll_override.dll.into(),
+            mode: DllOverrideMode::from_label(dll_override.mode.as_str()).unwrap_or_default(),
+        }
+    }
+}
+
+impl From<SchedulingConfig> for sixtyfps_generated_Main::SixtySchedulingConfig {
+    fn from(config: SchedulingConfig) -> Self {
+        let number = |value: Option<i32>| {
+            value
+                .map(|value| SharedString::from(value.to_string()))
+                .unwrap_or_default()
+        };
+
+        Self {
+            nice: number(config.nice),
+            cpu_affinity: SharedString::from(config.cpu_affinity),
+            io_class: SharedString::from(config.io_class.name()),
+            io_priority: number(config.io_priority),
+            rlimits: SharedString::from(
+                config
+                    .rlimits
+                    .iter()
+                    .map(|(name, limit)| format!("{}={}", name, limit))
+                    .collect::<Vec<String>>()
+                    .join("; "),
+            ),
+        }
+    }
+}
+
+impl From<SixtySchedulingConfig> for SchedulingConfig {
+    fn from(config: SixtySchedulingConfig) -> Self {
+        Self {
+            nice: config.nice.trim().parse().ok(),
+            cpu_affinity: config.cpu_affinity.trim().to_string(),
+            io_class: IoClass::from_name(config.io_class.as_str()).unwrap_or_default(),
+            io_priority: config.io_priority.trim().parse().ok(),
+            rlimits: config
+                .rlimits
+                .split(';')
+                .filter_map(|pair| pair.split_once('='))
+                .filter_map(|(name, limit)| {
+                    Some((name.trim().to_string(), limit.trim().parse().ok()?))
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<PlaytimeLimits> for sixtyfps_generated_Main::SixtyPlaytimeLimits {
+    fn from(limits: PlaytimeLimits) -> Self {
+        Self {
+            daily_minutes: SharedString::from(if limits.daily_minutes == 0 {
+                String::new()
+            } else {
+                limits.daily_minutes.to_string()
+            }),
+            allowed_hours: SharedString::from(
+                limits
+                    .allowed_hours
+                    .iter()
+                    .map(|(day, windows)| format!("{}={}", day, windows))
+                    .collect::<Vec<String>>()
+                    .join("; "),
+            ),
+            warn_before: SharedString::from(
+                limits
+                    .warn_before
+                    .map(|warn_before| {
+                        if warn_before.is_empty() {
+                            "0".to_string()
+                        } else {
+                            warn_before
+                                .iter()
+                                .map(|minutes| minutes.to_string())
+                                .collect::<Vec<String>>()
+                                .join(", ")
+                        }
+                    })
+                    .unwrap_or_default(),
+            ),
+            grace_seconds: SharedString::from(
+                limits
+                    .grace_seconds
+                    .map(|seconds| seconds.to_string())
+                    .unwrap_or_default(),
+            ),
+            remind_every: SharedString::from(
+                limits
+                    .remind_every
+                    .map(|minutes| minutes.to_string())
+                    .unwrap_or_default(),
+            ),
+        }
+    }
+}
+
+impl From<SixtyPlaytimeLimits> for PlaytimeLimits {
+    fn from(limits: SixtyPlaytimeLimits) -> Self {
+        Self {
+            daily_minutes: limits.daily_minutes.trim().parse().unwrap_or(0),
+            // Entered as day=windows pairs separated by semicolons
+            allowed_hours: limits
+                .allowed_hours
+                .split(';')
+                .filter_map(|pair| pair.split_once('='))
+                .map(|(day, windows)| (day.trim().to_string(), windows.trim().to_string()))
+                .collect(),
+            // These are left empty to follow the global config. No warnings at all are entered as 0
+            warn_before: (!limits.warn_before.trim().is_empty()).then(|| {
+                limits
+                    .warn_before
+                    .split(',')
+                    .filter_map(|minutes| minutes.trim().parse().ok())
+                    .filter(|minutes| *minutes > 0)
+                    .collect()
+            }),
+            grace_seconds: limits.grace_seconds.trim().parse().ok(),
+            remind_every: limits.remind_every.trim().parse().ok(),
+        }
+    }
+}
+
+fn check_playtime(limits: SixtyPlaytimeLimits) -> String {
+    let mut problems: Vec<String> = [
+        ("daily minutes", &limits.daily_minutes),
+        ("grace seconds", &limits.grace_seconds),
+        ("reminder minutes", &limits.remind_every),
+    ]
+    .iter()
+    .filter(|(_, value)| !value.trim().is_empty() && value.trim().parse::<u64>().is_err())
+    .map(|(name, value)| format!("Invalid {} {}", name, value))
+    .collect();
+    problems.extend(
+        limits
+            .allowed_hours
+            .split(';')
+            .filter(|pair| !pair.trim().is_empty() && !pair.contains('='))
+            .map(|pair| format!("Invalid allowed hours {}, use day=windows", pair.trim())),
+    );
+
+    problems.extend(PlaytimeLimits::from(limits).validate());
+    problems.join(". ")
+}
+
+// The pre-launch or post-exit commands shown in the game or global config form
+fn commands_model(
+    main_window: &Main,
+    is_pre_launch: bool,
+    is_game_config: bool,
+) -> sixtyfps::ModelHandle<SixtyConfigCommand> {
+    match (is_pre_launch, is_game_config) {
+        (true, true) => main_window.get_game_pre_launch_commands(),
+        (false, true) => main_window.get_game_post_exit_commands(),
+        (true, false) => main_window.get_global_pre_launch_commands(),
+        (false, false) => main_window.get_global_post_exit_commands(),
+    }
+}
+
+fn command_row(
+    main_window: &Main,
+    index: i32,
+    is_pre_launch: bool,
+    is_game_config: bool,
+) -> SixtyConfigCommand {
+    commands_model(main_window, is_pre_launch, is_game_config).row_data(index as usize)
+}
+
+// The paired actions shown in the game or global config form
+fn paired_actions_model(
+    main_window: &Main,
+    is_game_config: bool,
+) -> sixtyfps::ModelHandle<SixtyPairedAction> {
+    if is_game_config {
+        main_window.get_game_paired_actions()
+    } else {
+        main_window.get_global_paired_actions()
+    }
+}
+
+fn store_command(
+    config: &mut GameConfig,
+    index: i32,
+    command: SixtyConfigCommand,
+    is_pre_launch: bool,
+) {
+    if is_pre_launch {
+        config.pre_launch_commands[index as usize] = command.into();
+    } else {
+        config.post_exit_commands[index as usize] = command.into();
+    }
+}
+
+// Problems with the scheduling settings as entered in the form, checked against the CPUs of this
+// machine
+fn check_scheduling(config: SixtySchedulingConfig) -> String {
+    let mut problems: Vec<String> = [
+        ("nice level", &config.nice),
+        ("I/O priority", &config.io_priority),
+    ]
+    .iter()
+    .filter(|(_, value)| !value.trim().is_empty() && value.trim().parse::<i32>().is_err())
+    .map(|(name, value)| format!("Invalid {} {}", name, value))
+    .collect();
+    problems.extend(
+        config
+            .rlimits
+            .split(';')
+            .filter(|pair| !pair.trim().is_empty())
+            .filter(|pair| {
+                pair.split_once('=')
+                    .map_or(true, |(_, limit)| limit.trim().parse::<u64>().is_err())
+            })
+            .map(|pair| format!("Invalid resource limit {}", pair.trim())),
+    );
+
+    problems.extend(SchedulingConfig::from(config).validate(scheduling::cpu_count()));
+    problems.join(". ")
+}
+
+// The GPU selection as shown in the config editor, where the default GPU has a name of its own
+pub fn render_on(pci_slot: &str) -> SharedString {
+    SharedString::from(if pci_slot.is_empty() {
+        "default"
+    } else {
+        pci_slot
+    })
+}
+
 pub fn run(
     global_config: &'static Arc<Mutex<GameConfig>>,
     game_config: &'static Arc<Mutex<GameConfig>>,
     banner_path: &String,
     logo_path: &String,
-) -> bool {
+    state_dir: &String,
+    install_dir: Option<String>,
+    playtime_notice: String,
+    interrupted_sessions: Vec<Journal>,
+    game_update: Option<GameUpdate>,
+    session_log: Arc<SessionLog>,
+    catalogue: Vec<CatalogueEntry>,
+    is_proton: bool,
+    gpus: Vec<Gpu>,
+) -> Launch {
     let main_window = Main::new();
     let main_window_weak = main_window.as_weak();
     // Load the banner image from steam library cache
@@ -73,91 +740,57 @@ pub fn run(
         Err(why) => println!("Error loading logo: {:?}", why),
     }
 
-    main_window.set_game_launch_placeholder(sixtyfps::SharedString::from(
-        game_config
-            .lock()
-            .unwrap()
-            .placeholder_launch_command
-            .clone(),
-    ));
-    main_window.set_global_launch_placeholder(sixtyfps::SharedString::from(
-        global_config
-            .lock()
-            .unwrap()
-            .placeholder_launch_command
-            .clone(),
-    ));
-
-    // Convert the config options into something the UI code can understand and work on
-    let game_config_options: Vec<sixtyfps_generated_Main::SixtyConfigOption> = game_config
+    game_config
         .lock()
         .unwrap()
-        .placeholder_map
-        .iter()
-        .map(|option| (*option).clone().into())
-        .collect();
-
-    let global_config_options: Vec<sixtyfps_generated_Main::SixtyConfigOption> = global_config
+        .set_on_sixtyfps(&main_window, true);
+    global_config
         .lock()
         .unwrap()
-        .placeholder_map
-        .iter()
-        .map(|option| (*option).clone().into())
-        .collect();
+        .set_on_sixtyfps(&main_window, false);
+    main_window.set_playtime_notice(SharedString::from(playtime_notice));
+    main_window.set_proton_title(is_proton);
 
-    let game_pre_launch_commands: Vec<sixtyfps_generated_Main::SixtyConfigCommand> = game_config
-        .lock()
-        .unwrap()
-        .pre_launch_commands
-        .iter()
-        .map(|command| (*command).clone().into())
-        .collect();
-
-    let game_post_exit_commands: Vec<sixtyfps_generated_Main::SixtyConfigCommand> = game_config
-        .lock()
-        .unwrap()
-        .post_exit_commands
-        .iter()
-        .map(|command| (*command).clone().into())
+    let gpu_slots: Vec<SharedString> = std::iter::once(SharedString::from("default"))
+        .chain(gpus.iter().map(|gpu| SharedString::from(&gpu.pci_slot)))
         .collect();
+    main_window.set_gpu_slots(sixtyfps::ModelHandle::new(std::rc::Rc::new(
+        sixtyfps::VecModel::from(gpu_slots),
+    )));
+    let gpus = Rc::new(gpus);
 
-    let global_pre_launch_commands: Vec<sixtyfps_generated_Main::SixtyConfigCommand> =
-        global_config
-            .lock()
-            .unwrap()
-            .pre_launch_commands
+    if !interrupted_sessions.is_empty() {
+        let appids: Vec<&str> = interrupted_sessions
             .iter()
-            .map(|command| (*command).clone().into())
+            .map(|journal| journal.appid.as_str())
             .collect();
+        main_window.set_interrupted_notice(SharedString::from(format!(
+            "Interrupted sessions of {} never ran their post-exit commands",
+            appids.join(", ")
+        )));
+    }
+    let interrupted_sessions = Rc::new(RefCell::new(interrupted_sessions));
 
-    let global_post_exit_commands: Vec<sixtyfps_generated_Main::SixtyConfigCommand> = global_config
-        .lock()
-        .unwrap()
-        .post_exit_commands
-        .iter()
-        .map(|command| (*command).clone().into())
-        .collect();
-
-    main_window.set_global_pre_launch_commands(sixtyfps::ModelHandle::new(std::rc::Rc::new(
-        sixtyfps::VecModel::from(global_pre_launch_commands),
-    )));
-    main_window.set_global_post_exit_commands(sixtyfps::ModelHandle::new(std::rc::Rc::new(
-        sixtyfps::VecModel::from(global_post_exit_commands),
-    )));
+    main_window.set_preflight_notice(preflight_notice(
+        &game_config.lock().unwrap().preflight,
+        install_dir.as_deref(),
+    ));
 
-    main_window.set_game_pre_launch_commands(sixtyfps::ModelHandle::new(std::rc::Rc::new(
-        sixtyfps::VecModel::from(game_pre_launch_commands),
-    )));
-    main_window.set_game_post_exit_commands(sixtyfps::ModelHandle::new(std::rc::Rc::new(
-        sixtyfps::VecModel::from(game_post_exit_commands),
-    )));
+    if let Some(update) = game_update {
+        main_window.set_update_notice(SharedString::from(format!(
+            "The game was updated from build {} to {}, the tweaks made for it may no longer work",
+            update.previous, update.current
+        )));
+    }
 
-    main_window.set_game_config_options(sixtyfps::ModelHandle::new(std::rc::Rc::new(
-        sixtyfps::VecModel::from(game_config_options),
-    )));
-    main_window.set_global_config_options(sixtyfps::ModelHandle::new(std::rc::Rc::new(
-        sixtyfps::VecModel::from(global_config_options),
+    let catalogue_names: Vec<SharedString> = catalogue
+        .iter()
+        .map(|entry| SharedString::from(&entry.name))
+        .collect();
+    main_window.set_catalogue_names(sixtyfps::ModelHandle::new(std::rc::Rc::new(
+        sixtyfps::VecModel::from(catalogue_names),
     )));
+    let catalogue = Rc::new(catalogue);
 
     // Callbacks from the ui code are defined here
 
@@ -165,6 +798,227 @@ pub fn run(
         sixtyfps::quit_event_loop();
     });
 
+    main_window.on_check_condition(move |condition: SharedString| {
+        SharedString::from(condition::describe(&condition))
+    });
+
+    main_window.on_last_ran(closure!(clone state_dir, |command: SixtyConfigCommand| {
+        SharedString::from(frequency::describe_last_ran(&command.into(), &state_dir))
+    }));
+
+    main_window.on_check_tool(move |replace_with: SharedString| {
+        SharedString::from(tools::describe(&replace_with))
+    });
+
+    main_window.on_describe_catalogue_entry(closure!(clone catalogue, |name: SharedString| {
+        match catalogue.iter().find(|entry| entry.name == name.as_str()) {
+            Some(entry) => {
+                SharedString::from(format!("{}: {}", entry.description, entry.replace_with))
+            }
+            None => SharedString::default(),
+        }
+    }));
+
+    main_window.on_add_from_catalogue(closure!(clone main_window_weak, clone game_config, clone global_config, clone catalogue, |name: SharedString, is_game_config: bool| {
+        let main_window = main_window_weak.unwrap();
+        let mut config = if is_game_config { game_config.lock().unwrap() } else { global_config.lock().unwrap() };
+        if let Some(entry) = catalogue.iter().find(|entry| entry.name == name.as_str()) {
+            config.add_from_catalogue(entry);
+            config.set_on_sixtyfps(&main_window, is_game_config);
+        }
+    }));
+
+    main_window.on_sync_gamescope(closure!(clone game_config, clone global_config, |gamescope: SixtyGamescopeConfig, is_game_config: bool| {
+        let mut config = if is_game_config { game_config.lock().unwrap() } else { global_config.lock().unwrap() };
+        config.gamescope = gamescope.into();
+    }));
+
+    main_window.on_check_gamescope(
+        move |gamescope: SixtyGamescopeConfig, launch_command: SharedString| {
+            SharedString::from(check_gamescope(gamescope, &launch_command))
+        },
+    );
+
+    main_window.on_import_gamescope(closure!(clone main_window_weak, clone game_config, clone global_config, |is_game_config: bool| {
+        let main_window = main_window_weak.unwrap();
+        let mut config = if is_game_config { game_config.lock().unwrap() } else { global_config.lock().unwrap() };
+        if config.import_gamescope() {
+            config.set_on_sixtyfps(&main_window, is_game_config);
+        }
+    }));
+
+    main_window.on_sync_mangohud(closure!(clone game_config, clone global_config, |mangohud: SixtyMangoHudConfig, is_game_config: bool| {
+        let mut config = if is_game_config { game_config.lock().unwrap() } else { global_config.lock().unwrap() };
+        config.mangohud = mangohud.into();
+    }));
+
+    main_window.on_check_mangohud(move |mangohud: SixtyMangoHudConfig| {
+        SharedString::from(check_mangohud(mangohud))
+    });
+
+    main_window.on_sync_proton(closure!(clone game_config, clone global_config, |proton: SixtyProtonConfig, is_game_config: bool| {
+        let mut config = if is_game_config { game_config.lock().unwrap() } else { global_config.lock().unwrap() };
+        config.proton = ProtonConfig { dll_overrides: std::mem::take(&mut config.proton.dll_overrides), ..proton.into() };
+    }));
+
+    main_window.on_sync_dll_override(closure!(clone game_config, clone global_config, |index: i32, dll_override: SixtyDllOverride, is_game_config: bool| {
+        let mut config = if is_game_config { game_config.lock().unwrap() } else { global_config.lock().unwrap() };
+        config.proton.dll_overrides[index as usize] = dll_override.into();
+    }));
+
+    main_window.on_add_dll_override(closure!(clone main_window_weak, clone game_config, clone global_config, |is_game_config: bool| {
+        let main_window = main_window_weak.unwrap();
+        let mut config = if is_game_config { game_config.lock().unwrap() } else { global_config.lock().unwrap() };
+        config.proton.dll_overrides.push(DllOverride::default());
+        config.set_on_sixtyfps(&main_window, is_game_config);
+    }));
+
+    main_window.on_remove_dll_override(closure!(clone main_window_weak, clone game_config, clone global_config, |index: i32, is_game_config: bool| {
+        let main_window = main_window_weak.unwrap();
+        let mut config = if is_game_config { game_config.lock().unwrap() } else { global_config.lock().unwrap() };
+        config.proton.dll_overrides.remove(index as usize);
+        config.set_on_sixtyfps(&main_window, is_game_config);
+    }));
+
+    main_window.on_sync_render_on(closure!(clone game_config, clone global_config, |pci_slot: SharedString, is_game_config: bool| {
+        let mut config = if is_game_config { game_config.lock().unwrap() } else { global_config.lock().unwrap() };
+        config.render_on = if pci_slot.as_str() == "default" { String::new() } else { pci_slot.into() };
+    }));
+
+    main_window.on_describe_gpu(closure!(clone gpus, |pci_slot: SharedString| {
+        match gpus.iter().find(|gpu| gpu.pci_slot == pci_slot.as_str()) {
+            Some(gpu) => SharedString::from(gpu.describe()),
+            None if pci_slot.as_str() == "default" => SharedString::from("The GPU used for everything else"),
+            None => SharedString::from("Not found"),
+        }
+    }));
+
+    main_window.on_sync_scheduling(closure!(clone game_config, clone global_config, |scheduling: SixtySchedulingConfig, is_game_config: bool| {
+        let mut config = if is_game_config { game_config.lock().unwrap() } else { global_config.lock().unwrap() };
+        config.scheduling = scheduling.into();
+    }));
+
+    main_window.on_check_scheduling(move |scheduling: SixtySchedulingConfig| {
+        SharedString::from(check_scheduling(scheduling))
+    });
+
+    main_window.on_sync_playtime(closure!(clone game_config, clone global_config, |playtime: SixtyPlaytimeLimits, is_game_config: bool| {
+        let mut config = if is_game_config { game_config.lock().unwrap() } else { global_config.lock().unwrap() };
+        config.playtime = playtime.into();
+    }));
+
+    main_window.on_check_playtime(move |playtime: SixtyPlaytimeLimits| {
+        SharedString::from(check_playtime(playtime))
+    });
+
+    main_window.on_sync_paired_action(closure!(clone game_config, clone global_config, |index: i32, action: SixtyPairedAction, is_game_config: bool| {
+        let mut config = if is_game_config { game_config.lock().unwrap() } else { global_config.lock().unwrap() };
+        config.paired_actions[index as usize] = action.into();
+    }));
+
+    main_window.on_add_paired_action(closure!(clone main_window_weak, clone game_config, clone global_config, |is_game_config: bool| {
+        let main_window = main_window_weak.unwrap();
+        let mut config = if is_game_config { game_config.lock().unwrap() } else { global_config.lock().unwrap() };
+        config.paired_actions.push(PairedAction { enabled: true, ..Default::default() });
+        config.set_on_sixtyfps(&main_window, is_game_config);
+    }));
+
+    main_window.on_remove_paired_action(closure!(clone main_window_weak, clone game_config, clone global_config, |index: i32, is_game_config: bool| {
+        let main_window = main_window_weak.unwrap();
+        let mut config = if is_game_config { game_config.lock().unwrap() } else { global_config.lock().unwrap() };
+        config.paired_actions.remove(index as usize);
+        config.set_on_sixtyfps(&main_window, is_game_config);
+    }));
+
+    main_window.on_sync_action_env(closure!(clone main_window_weak, clone game_config, clone global_config, |index: i32, var_index: i32, env_var: SixtyEnvVar, is_game_config: bool| {
+        let main_window = main_window_weak.unwrap();
+        let mut config = if is_game_config { game_config.lock().unwrap() } else { global_config.lock().unwrap() };
+        let action = paired_actions_model(&main_window, is_game_config).row_data(index as usize);
+        action.env.set_row_data(var_index as usize, env_var);
+        config.paired_actions[index as usize] = action.into();
+    }));
+
+    main_window.on_add_action_env(closure!(clone main_window_weak, clone game_config, clone global_config, |index: i32, is_game_config: bool| {
+        let main_window = main_window_weak.unwrap();
+        let mut config = if is_game_config { game_config.lock().unwrap() } else { global_config.lock().unwrap() };
+        let actions = paired_actions_model(&main_window, is_game_config);
+        let mut action = actions.row_data(index as usize);
+        let mut env: Vec<SixtyEnvVar> = (0..action.env.row_count()).map(|row| action.env.row_data(row)).collect();
+        env.push(SixtyEnvVar::default());
+        action.env = sixtyfps::ModelHandle::new(std::rc::Rc::new(sixtyfps::VecModel::from(env)));
+        actions.set_row_data(index as usize, action.clone());
+        config.paired_actions[index as usize] = action.into();
+    }));
+
+    main_window.on_remove_action_env(closure!(clone main_window_weak, clone game_config, clone global_config, |index: i32, var_index: i32, is_game_config: bool| {
+        let main_window = main_window_weak.unwrap();
+        let mut config = if is_game_config { game_config.lock().unwrap() } else { global_config.lock().unwrap() };
+        let actions = paired_actions_model(&main_window, is_game_config);
+        let mut action = actions.row_data(index as usize);
+        let mut env: Vec<SixtyEnvVar> = (0..action.env.row_count()).map(|row| action.env.row_data(row)).collect();
+        env.remove(var_index as usize);
+        action.env = sixtyfps::ModelHandle::new(std::rc::Rc::new(sixtyfps::VecModel::from(env)));
+        actions.set_row_data(index as usize, action.clone());
+        config.paired_actions[index as usize] = action.into();
+    }));
+
+    main_window.on_sync_snapshots(closure!(clone game_config, clone global_config, |snapshots: SixtySnapshotConfig, is_game_config: bool| {
+        let mut config = if is_game_config { game_config.lock().unwrap() } else { global_config.lock().unwrap() };
+        config.snapshots = snapshots.into();
+    }));
+
+    main_window.on_sync_overlay(closure!(clone game_config, clone global_config, |index: i32, overlay: SixtyFileOverlay, is_game_config: bool| {
+        let mut config = if is_game_config { game_config.lock().unwrap() } else { global_config.lock().unwrap() };
+        config.overlays[index as usize] = overlay.into();
+    }));
+
+    main_window.on_add_overlay(closure!(clone main_window_weak, clone game_config, clone global_config, |is_game_config: bool| {
+        let main_window = main_window_weak.unwrap();
+        let mut config = if is_game_config { game_config.lock().unwrap() } else { global_config.lock().unwrap() };
+        config.overlays.push(FileOverlay { enabled: true, ..Default::default() });
+        config.set_on_sixtyfps(&main_window, is_game_config);
+    }));
+
+    main_window.on_remove_overlay(closure!(clone main_window_weak, clone game_config, clone global_config, |index: i32, is_game_config: bool| {
+        let main_window = main_window_weak.unwrap();
+        let mut config = if is_game_config { game_config.lock().unwrap() } else { global_config.lock().unwrap() };
+        config.overlays.remove(index as usize);
+        config.set_on_sixtyfps(&main_window, is_game_config);
+    }));
+
+    main_window.on_sync_preflight_check(closure!(clone game_config, clone global_config, |index: i32, check: SixtyPreflightCheck, is_game_config: bool| {
+        let mut config = if is_game_config { game_config.lock().unwrap() } else { global_config.lock().unwrap() };
+        config.preflight[index as usize] = check.into();
+    }));
+
+    main_window.on_add_preflight_check(closure!(clone main_window_weak, clone game_config, clone global_config, |is_game_config: bool| {
+        let main_window = main_window_weak.unwrap();
+        let mut config = if is_game_config { game_config.lock().unwrap() } else { global_config.lock().unwrap() };
+        config.preflight.push(PreflightCheck { enabled: true, ..Default::default() });
+        config.set_on_sixtyfps(&main_window, is_game_config);
+    }));
+
+    main_window.on_remove_preflight_check(closure!(clone main_window_weak, clone game_config, clone global_config, |index: i32, is_game_config: bool| {
+        let main_window = main_window_weak.unwrap();
+        let mut config = if is_game_config { game_config.lock().unwrap() } else { global_config.lock().unwrap() };
+        config.preflight.remove(index as usize);
+        config.set_on_sixtyfps(&main_window, is_game_config);
+    }));
+
+    main_window.on_recheck_preflight(
+        closure!(clone main_window_weak, clone game_config, clone install_dir, || {
+            main_window_weak.unwrap().set_preflight_notice(preflight_notice(
+                &game_config.lock().unwrap().preflight,
+                install_dir.as_deref(),
+            ));
+        }),
+    );
+
+    main_window.on_sync_safe_mode(closure!(clone game_config, clone global_config, |safe_mode: SixtySafeModeConfig, is_game_config: bool| {
+        let mut config = if is_game_config { game_config.lock().unwrap() } else { global_config.lock().unwrap() };
+        config.safe_mode = safe_mode.into();
+    }));
+
     main_window.on_print_val(move |index: i32| {
         println!("{}", index);
     });
@@ -220,6 +1074,38 @@ pub fn run(
         }),
     );
 
+    main_window.on_sync_env(closure!(clone main_window_weak, clone game_config, clone global_config, |index: i32, var_index: i32, env_var: SixtyEnvVar, is_pre_launch: bool, is_game_config: bool| {
+        let main_window = main_window_weak.unwrap();
+        let mut config = if is_game_config { game_config.lock().unwrap() } else { global_config.lock().unwrap() };
+        // The form keeps the variables in the order they were entered, so the row is changed there
+        // and the whole command taken from it
+        let command = command_row(&main_window, index, is_pre_launch, is_game_config);
+        command.env.set_row_data(var_index as usize, env_var);
+        store_command(&mut config, index, command, is_pre_launch);
+    }));
+
+    main_window.on_add_env(closure!(clone main_window_weak, clone game_config, clone global_config, |index: i32, is_pre_launch: bool, is_game_config: bool| {
+        let main_window = main_window_weak.unwrap();
+        let mut config = if is_game_config { game_config.lock().unwrap() } else { global_config.lock().unwrap() };
+        let mut command = command_row(&main_window, index, is_pre_launch, is_game_config);
+        let mut env: Vec<SixtyEnvVar> = (0..command.env.row_count()).map(|row| command.env.row_data(row)).collect();
+        env.push(SixtyEnvVar::default());
+        command.env = sixtyfps::ModelHandle::new(std::rc::Rc::new(sixtyfps::VecModel::from(env)));
+        commands_model(&main_window, is_pre_launch, is_game_config).set_row_data(index as usize, command.clone());
+        store_command(&mut config, index, command, is_pre_launch);
+    }));
+
+    main_window.on_remove_env(closure!(clone main_window_weak, clone game_config, clone global_config, |index: i32, var_index: i32, is_pre_launch: bool, is_game_config: bool| {
+        let main_window = main_window_weak.unwrap();
+        let mut config = if is_game_config { game_config.lock().unwrap() } else { global_config.lock().unwrap() };
+        let mut command = command_row(&main_window, index, is_pre_launch, is_game_config);
+        let mut env: Vec<SixtyEnvVar> = (0..command.env.row_count()).map(|row| command.env.row_data(row)).collect();
+        env.remove(var_index as usize);
+        command.env = sixtyfps::ModelHandle::new(std::rc::Rc::new(sixtyfps::VecModel::from(env)));
+        commands_model(&main_window, is_pre_launch, is_game_config).set_row_data(index as usize, command.clone());
+        store_command(&mut config, index, command, is_pre_launch);
+    }));
+
     main_window.on_sync_placeholder_string(closure!(clone game_config, clone global_config, |text: SharedString, is_game_config: bool| {
         let mut config = if is_game_config { game_config.lock().unwrap() } else { global_config.lock().unwrap() };
         config.placeholder_launch_command = text.into();
@@ -272,7 +1158,31 @@ pub fn run(
         }),
     );
 
+    main_window.on_recover_interrupted_sessions(
+        closure!(clone main_window_weak, clone interrupted_sessions, || {
+            for journal in interrupted_sessions.borrow_mut().drain(..) {
+                journal.recover(&session_log);
+            }
+            main_window_weak.unwrap().set_interrupted_notice(SharedString::default());
+        }),
+    );
+
+    main_window.on_discard_interrupted_sessions(
+        closure!(clone main_window_weak, clone interrupted_sessions, || {
+            for journal in interrupted_sessions.borrow_mut().drain(..) {
+                journal.remove();
+            }
+            main_window_weak.unwrap().set_interrupted_notice(SharedString::default());
+        }),
+    );
+
     main_window.run();
 
-    main_window.get_launch_cancelled()
+    if main_window.get_launch_cancelled() {
+        Launch::Cancelled
+    } else if main_window.get_start_in_safe_mode() {
+        Launch::SafeMode
+    } else {
+        Launch::Normal
+    }
 }